@@ -0,0 +1,124 @@
+@group(0) @binding(0)
+var cells: texture_2d<u32>;
+
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+// the colors of palette.rs, by material id
+struct PaletteColor {
+    color: vec4<f32>,
+    variation: f32,
+    filled: u32,
+};
+@group(0) @binding(2)
+var<storage, read> palette: array<PaletteColor>;
+
+// the changed flags of chunks.rs, both halves
+@group(0) @binding(3)
+var<storage, read> chunks: array<u32>;
+
+const workgroup_size = #{WORKGROUP_SIZE}u;
+
+// The variation comes from the seed in w that a grain gets when it is made and
+// keeps while it moves, so a grain keeps its shade as it falls.
+fn materialColor(cell: vec4<u32>) -> vec4<f32> {
+  let entry = palette[min(cell.x, arrayLength(&palette) - 1u)];
+  let noise = f32(cell.w) / 4294967295.0 - 0.5;
+  let rgb = clamp(entry.color.rgb + noise * entry.variation, vec3<f32>(0.0), vec3<f32>(1.0));
+  return vec4<f32>(rgb, entry.color.a);
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn draw(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  // the last workgroups hang over the edge unless the size is a multiple of the workgroup size
+  if (any(invocation_id.xy >= textureDimensions(output))) {
+    return;
+  }
+  let location = vec2<i32>(invocation_id.xy);
+  textureStore(output, location, materialColor(textureLoad(cells, location, 0)));
+}
+
+// blue below room temperature, then red, yellow and white up to 1000 degrees above it
+fn heatColor(temperature: f32) -> vec3<f32> {
+  if (temperature < 20.0) {
+    return vec3<f32>(0.0, 0.0, clamp((20.0 - temperature) / 40.0, 0.0, 1.0));
+  }
+  let heat = clamp((temperature - 20.0) / 1000.0, 0.0, 1.0) * 3.0;
+  return clamp(vec3<f32>(heat, heat - 1.0, heat - 2.0), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn drawHeatmap(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  if (any(invocation_id.xy >= textureDimensions(output))) {
+    return;
+  }
+  let location = vec2<i32>(invocation_id.xy);
+  let cell = textureLoad(cells, location, 0);
+  let color = materialColor(cell);
+  let rgb = mix(color.rgb * color.a, heatColor(bitcast<f32>(cell.z)), 0.75);
+  textureStore(output, location, vec4<f32>(rgb, 1.0));
+}
+
+// how much the filled cells around a grain darken it, and how much its top edge
+// lights up and its bottom edge falls into shadow
+const occlusion_radius = 2;
+const occlusion_strength = 0.4;
+const edge_strength = 0.15;
+
+// gases and the world outside let light through
+fn isFilled(location: vec2<i32>) -> bool {
+  if (any(location < vec2<i32>(0)) || any(vec2<u32>(location) >= textureDimensions(cells))) {
+    return false;
+  }
+  let id = textureLoad(cells, location, 0).x;
+  return id < arrayLength(&palette) && palette[id].filled != 0u;
+}
+
+// draw with fake ambient occlusion and light from the top of the screen, only
+// changes how the cells look
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn drawShaded(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  if (any(invocation_id.xy >= textureDimensions(output))) {
+    return;
+  }
+  let location = vec2<i32>(invocation_id.xy);
+  let color = materialColor(textureLoad(cells, location, 0));
+  if (!isFilled(location)) {
+    textureStore(output, location, color);
+    return;
+  }
+
+  var filled = 0.0;
+  for (var y = -occlusion_radius; y <= occlusion_radius; y++) {
+    for (var x = -occlusion_radius; x <= occlusion_radius; x++) {
+      filled += select(0.0, 1.0, isFilled(location + vec2<i32>(x, y)));
+    }
+  }
+  let side = f32(2 * occlusion_radius + 1);
+  // about as bright as without shading on a flat surface, half the area filled
+  var light = 1.0 + occlusion_strength * (0.5 - filled / (side * side));
+  if (!isFilled(location + vec2<i32>(0, -1))) {
+    light += edge_strength;
+  }
+  if (!isFilled(location + vec2<i32>(0, 1))) {
+    light -= edge_strength;
+  }
+  let rgb = clamp(color.rgb * light, vec3<f32>(0.0), vec3<f32>(1.0));
+  textureStore(output, location, vec4<f32>(rgb, color.a));
+}
+
+// outlines the chunks that changed in the last two ticks on top of what draw wrote
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn drawChunks(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let size = textureDimensions(output);
+  if (any(invocation_id.xy >= size)) {
+    return;
+  }
+  let grid = (size / 2u + 1u + workgroup_size - 1u) / workgroup_size;
+  let chunk = invocation_id.xy / 2u / workgroup_size;
+  let index = chunk.y * grid.x + chunk.x;
+  let edge = invocation_id.xy % (2u * workgroup_size);
+  if ((chunks[index] | chunks[grid.x * grid.y + index]) != 0u && (edge.x == 0u || edge.y == 0u)) {
+    textureStore(output, vec2<i32>(invocation_id.xy), vec4<f32>(0.0, 1.0, 0.0, 1.0));
+  }
+}
@@ -0,0 +1,51 @@
+#import "shaders/random.wgsl"::hash
+
+@group(0) @binding(0)
+var cells: texture_2d<u32>;
+
+struct Step {
+    seed: u32,
+    tick: u32,
+    phase: u32,
+};
+@group(0) @binding(1)
+var<uniform> step: Step;
+
+// per slot one more than the tick it was hashed after, the sum and the xor of
+// the hashes of all cells, see replay.rs
+@group(0) @binding(2)
+var<storage, read_write> hashes: array<atomic<u32>>;
+
+const slots = #{HASH_SLOTS}u;
+
+// zero at the start of every workgroup
+var<workgroup> local_sum: atomic<u32>;
+var<workgroup> local_xor: atomic<u32>;
+
+// Sums and xors are the same in whatever order the cells are added up, so two
+// worlds hash the same exactly when (up to collisions) all their cells do.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn hashCells(
+  @builtin(global_invocation_id) invocation_id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  let size = textureDimensions(cells);
+  if (all(invocation_id.xy < size)) {
+    let cell = textureLoad(cells, vec2<i32>(invocation_id.xy), 0);
+    let index = invocation_id.y * size.x + invocation_id.x;
+    // the grain seed in w only changes how cells are drawn, it is hashed as the
+    // 0 it was before grains had one so that older recordings still check out
+    let value = hash(cell.x ^ hash(cell.y ^ hash(cell.z ^ hash(0u ^ hash(index)))));
+    atomicAdd(&local_sum, value);
+    atomicXor(&local_xor, hash(value));
+  }
+
+  workgroupBarrier();
+
+  if (local_index == 0u) {
+    let slot = step.tick % slots * 3u;
+    atomicStore(&hashes[slot], step.tick + 1u);
+    atomicAdd(&hashes[slot + 1u], atomicLoad(&local_sum));
+    atomicXor(&hashes[slot + 2u], atomicLoad(&local_xor));
+  }
+}
@@ -0,0 +1,12 @@
+// The random numbers of the kernels, the same as `hash` in sim.rs so the CPU
+// backend simulates the same world.
+fn hash(value: u32) -> u32 {
+    var state = value;
+    state = state ^ 2747636419u;
+    state = state * 2654435769u;
+    state = state ^ state >> 16u;
+    state = state * 2654435769u;
+    state = state ^ state >> 16u;
+    state = state * 2654435769u;
+    return state;
+}
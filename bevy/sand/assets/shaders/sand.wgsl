@@ -1,185 +1,790 @@
+#import "shaders/random.wgsl"::hash
+
 @group(0) @binding(0)
-var texture: texture_storage_2d<rgba8unorm, read_write>;
+var src: texture_2d<u32>;
+
+@group(0) @binding(1)
+var dst: texture_storage_2d<rgba32uint, write>;
 
 struct Time {
     seconds_since_startup: f32,
 };
-@group(0) @binding(1)
+@group(0) @binding(2)
 var<uniform> time: Time;
 
-fn hash(value: u32) -> u32 {
-    var state = value;
-    state = state ^ 2747636419u;
-    state = state * 2654435769u;
-    state = state ^ state >> 16u;
-    state = state * 2654435769u;
-    state = state ^ state >> 16u;
-    state = state * 2654435769u;
-    return state;
-}
-
-fn randomFloat(value: i32) -> f32 {
-    return f32(hash(u32(value))) / 4294967295.0;
-}
-
-const empty = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-const sand = vec4<f32>(1.0, 1.0, 0.0, 1.0);
-const brick = vec4<f32>(1.0, 0.0, 0.2, 1.0);
-const water = vec4<f32>(0.0, 0.0, 1.0, 1.0);
-
-fn drawBricks() {
-  for (var y = 0; y < 1280; y++) {
-    for (var x = 0; x < 1280; x++) {
-      let roughX = x / 200;
-      let roughY = y / 100;
-      let m = roughY % 2;
-      let k = roughY % 3;
-      if (k == 0 && randomFloat(roughX * roughY) > 0.4 && roughX % 2 == m && roughY % 2 == m) {
-        var xCor = x + i32((0.5 - randomFloat(roughX * roughY * i32(time.seconds_since_startup * 1000.0))) * 100.0);
-        textureStore(texture, vec2<i32>(xCor, y), brick);
+struct Step {
+    seed: u32,
+    tick: u32,
+    phase: u32,
+};
+@group(0) @binding(3)
+var<uniform> step: Step;
+
+// kinds, in the order of MaterialKind
+const kind_static = 0u;
+const kind_powder = 1u;
+const kind_liquid = 2u;
+const kind_gas = 3u;
+
+struct Material {
+    color: vec4<f32>,
+    kind: u32,
+    density: f32,
+    dispersion: f32,
+    color_variation: f32,
+    temperature: f32,
+    conductivity: f32,
+    heat_source: u32,
+    above_temperature: f32,
+    above_into: u32,
+    below_temperature: f32,
+    below_into: u32,
+    decay_chance: f32,
+    decay_into: u32,
+};
+
+// id of a transition that does not happen
+const none = 0xffffffffu;
+@group(0) @binding(4)
+var<storage, read> materials: array<Material>;
+
+// shapes, in the order of BrushShape
+const shape_circle = 0u;
+const shape_square = 1u;
+
+struct Brush {
+    start: vec2<f32>,
+    end: vec2<f32>,
+    origin: vec2<i32>,
+    material: u32,
+    radius: f32,
+    shape: u32,
+};
+@group(0) @binding(5)
+var<uniform> brush: Brush;
+
+// see chunks.rs
+@group(0) @binding(6)
+var<storage, read_write> changed: array<atomic<u32>>;
+
+@group(0) @binding(7)
+var<storage, read_write> active_chunks: array<u32>;
+
+// what the passes of the tick do with a chunk
+const skipped = 0u;
+const copied = 1u;
+const updated = 2u;
+@group(0) @binding(8)
+var<storage, read_write> modes: array<u32>;
+
+// see gravity.rs
+struct Gravity {
+    down: vec2<i32>,
+    wind: vec2<f32>,
+    field_size: vec2<u32>,
+    field_cell_size: u32,
+};
+@group(0) @binding(9)
+var<uniform> gravity: Gravity;
+
+@group(0) @binding(10)
+var<storage, read> wind_field: array<vec2<f32>>;
+
+// cells moved by the update passes since the StatsNode last took the count,
+// see stats.rs
+@group(0) @binding(11)
+var<storage, read_write> moves: atomic<u32>;
+
+// see bodies.rs, only bound for stampBodies and placeDisplaced
+const shape_ball = 0u;
+const shape_box = 1u;
+
+struct Body {
+    center: vec2<f32>,
+    half_size: vec2<f32>,
+    // cos and sin of the angle the body is turned by
+    rotation: vec2<f32>,
+    shape: u32,
+};
+struct Bodies {
+    count: u32,
+    material: u32,
+    bodies: array<Body>,
+};
+@group(1) @binding(0)
+var<storage, read> bodies: Bodies;
+
+// per cell, one more than the index of the cell pushed into it
+@group(1) @binding(1)
+var<storage, read_write> claims: array<atomic<u32>>;
+
+// per body, the cells around it, how many of those hold it up and their
+// total density in 1/256
+@group(1) @binding(2)
+var<storage, read_write> body_support: array<atomic<u32>>;
+
+// the arguments of the indirect update dispatch, only bound for resetSchedule
+// and schedule so that the update pass does not bind what it dispatches with
+struct Dispatch {
+    x: atomic<u32>,
+    y: u32,
+    z: u32,
+};
+@group(1) @binding(3)
+var<storage, read_write> dispatch: Dispatch;
+
+const workgroup_size = #{WORKGROUP_SIZE}u;
+const invocations = workgroup_size * workgroup_size;
+
+// the first material in sand.materials.ron
+const empty = 0u;
+
+// the ids of the materials the scenes are built from, see scene.rs
+struct SceneMaterials {
+    sand: u32,
+    water: u32,
+    brick: u32,
+};
+@group(0) @binding(12)
+var<uniform> scene: SceneMaterials;
+
+// cell flags
+const moved = 1u;
+
+// `seed` is what the color of a grain varies by, see draw.wgsl. It moves with
+// the grain and nothing but drawing reads it.
+struct Cell {
+  material: u32,
+  flags: u32,
+  temperature: f32,
+  seed: u32,
+};
+
+fn writeCell(location: vec2<i32>, cell: Cell) {
+  textureStore(dst, location, vec4<u32>(cell.material, cell.flags, bitcast<u32>(cell.temperature), cell.seed));
+}
+
+const ambient_temperature = 20.0;
+
+// a cell of the material at the temperature it starts at
+fn newCell(material: u32) -> Cell {
+  var temperature = ambient_temperature;
+  if (material < arrayLength(&materials)) {
+    temperature = materials[material].temperature;
+  }
+  return Cell(material, 0u, temperature, 0u);
+}
+
+// a new cell with a seed that depends on where and in which tick it was made
+fn newGrain(material: u32, location: vec2<i32>) -> Cell {
+  var cell = newCell(material);
+  cell.seed = hash(hash(step.seed ^ step.tick) ^ hash(u32(location.x) ^ hash(u32(location.y))));
+  return cell;
+}
+
+// in [0, 1], the same for a location every time the scene is built from a seed
+fn sceneRandom(location: vec2<i32>, salt: u32) -> f32 {
+  let value = hash(step.seed ^ hash(salt ^ hash(u32(location.x) ^ hash(u32(location.y)))));
+  return f32(value) / 4294967295.0;
+}
+
+fn waterNoise(location: vec2<i32>) -> bool {
+  return sceneRandom(location, 1u) > 0.9;
+}
+
+fn sandNoise(location: vec2<i32>) -> bool {
+  return sceneRandom(location, 2u) > 0.9;
+}
+
+// 200x100 bricks on every third row, on alternating columns, some left out and
+// each shifted sideways by up to 50 cells
+const brick_size = vec2<i32>(200, 100);
+
+fn isBrick(location: vec2<i32>) -> bool {
+  let row = location.y / brick_size.y;
+  if (row % 3 != 0) {
+    return false;
+  }
+  // a shifted brick can cover cells of the columns next to its own
+  let column = location.x / brick_size.x;
+  for (var candidate = max(column - 1, 0); candidate <= column + 1; candidate++) {
+    let rough = vec2<i32>(candidate, row);
+    if (candidate % 2 != row % 2 || sceneRandom(rough, 3u) <= 0.4) {
+      continue;
+    }
+    let shift = i32((0.5 - sceneRandom(rough, 4u)) * 100.0);
+    let x = location.x - shift;
+    if (x >= 0 && x / brick_size.x == candidate && x < i32(textureDimensions(dst).x)) {
+      return true;
+    }
+  }
+  return false;
+}
+
+// One init kernel per SceneGenerator. Each invocation decides the material of
+// its own cell only, so every cell is written exactly once.
+
+fn noise(location: vec2<i32>) -> u32 {
+  if (waterNoise(location)) {
+    return scene.water;
+  } else if (sandNoise(location)) {
+    return scene.sand;
+  }
+  return empty;
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn initEmpty(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let location = vec2<i32>(invocation_id.xy);
+  storeCell(location, newGrain(empty, location));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn initNoise(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let location = vec2<i32>(invocation_id.xy);
+  storeCell(location, newGrain(noise(location), location));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn initBricks(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let location = vec2<i32>(invocation_id.xy);
+  storeCell(location, newGrain(select(empty, scene.brick, isBrick(location)), location));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn initNoiseAndBricks(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let location = vec2<i32>(invocation_id.xy);
+  storeCell(location, newGrain(select(noise(location), scene.brick, isBrick(location)), location));
+}
+
+fn inBounds(location: vec2<i32>) -> bool {
+  let size = vec2<i32>(textureDimensions(src));
+  return all(location >= vec2<i32>(0)) && all(location < size);
+}
+
+// anything not in the registry, like the cells outside the world, never moves
+const outside = 0xffffffffu;
+
+fn isMovable(cell: Cell) -> bool {
+  return cell.material < arrayLength(&materials) && materials[cell.material].kind != kind_static;
+}
+
+fn isFluid(cell: Cell) -> bool {
+  // outside has no material to look up
+  if (!isMovable(cell)) {
+    return false;
+  }
+  let kind = materials[cell.material].kind;
+  return kind == kind_liquid || kind == kind_gas;
+}
+
+fn bothGases(a: Cell, b: Cell) -> bool {
+  return isFluid(a) && isFluid(b) && materials[a.material].kind == kind_gas && materials[b.material].kind == kind_gas;
+}
+
+// true if upper may trade places with lower
+fn sinks(upper: Cell, lower: Cell) -> bool {
+  return isMovable(upper) && isMovable(lower) && materials[upper.material].density > materials[lower.material].density;
+}
+
+// true if two different fluids next to each other trade places, chance is in [0, 1)
+fn spreads(a: Cell, b: Cell, chance: f32) -> bool {
+  if (!isFluid(a) || !isFluid(b) || a.material == b.material) {
+    return false;
+  }
+  return chance < max(materials[a.material].dispersion, materials[b.material].dispersion);
+}
+
+// cells outside the world are never written back
+fn loadCell(location: vec2<i32>) -> Cell {
+  if (!inBounds(location)) {
+    return Cell(outside, 0u, 0.0, 0u);
+  }
+  let texel = textureLoad(src, location, 0);
+  return Cell(texel.x, texel.y & ~moved, bitcast<f32>(texel.z), texel.w);
+}
+
+fn storeCell(location: vec2<i32>, cell: Cell) {
+  if (inBounds(location)) {
+    writeCell(location, cell);
+  }
+}
+
+fn conductivity(cell: Cell) -> f32 {
+  if (cell.material >= arrayLength(&materials)) {
+    return 0.0;
+  }
+  return materials[cell.material].conductivity;
+}
+
+// heat flowing from a to b, limited by the worse conductor of the two
+fn heatFlow(a: Cell, b: Cell) -> f32 {
+  return (a.temperature - b.temperature) * min(conductivity(a), conductivity(b)) * 0.25;
+}
+
+// neighbours in the block even out their temperatures, the block as a whole
+// neither gains nor loses heat
+fn exchangeHeat(cells: ptr<function, array<Cell, 4>>) {
+  let top = heatFlow((*cells)[0], (*cells)[1]);
+  let bottom = heatFlow((*cells)[2], (*cells)[3]);
+  let left = heatFlow((*cells)[0], (*cells)[2]);
+  let right = heatFlow((*cells)[1], (*cells)[3]);
+  (*cells)[0].temperature -= top + left;
+  (*cells)[1].temperature += top - right;
+  (*cells)[2].temperature += left - bottom;
+  (*cells)[3].temperature += bottom + right;
+}
+
+// heat sources keep their temperature, anything may turn into another material
+fn react(cell: Cell, random: u32) -> Cell {
+  if (cell.material >= arrayLength(&materials)) {
+    return cell;
+  }
+  let material = materials[cell.material];
+  var result = cell;
+  if (material.heat_source != 0u) {
+    result.temperature = material.temperature;
+  }
+  var into = none;
+  if (material.above_into != none && cell.temperature > material.above_temperature) {
+    into = material.above_into;
+  } else if (material.below_into != none && cell.temperature < material.below_temperature) {
+    into = material.below_into;
+  } else if (material.decay_into != none && f32(random & 0xffffu) / 65536.0 < material.decay_chance) {
+    into = material.decay_into;
+  }
+  if (into != none) {
+    result.material = into;
+    result.flags |= moved;
+  }
+  return result;
+}
+
+// what is to the right when looking at the world with gravity pointing down
+fn lateral() -> vec2<i32> {
+  return vec2<i32>(gravity.down.y, -gravity.down.x);
+}
+
+// The update rules are written for gravity pointing down. The cell at `index`
+// of a block as they see it is at this offset from its top left corner.
+fn blockOffset(index: i32) -> vec2<i32> {
+  let corner = vec2<i32>(index % 2, index / 2) * 2 - 1;
+  return (lateral() * corner.x + gravity.down * corner.y + 1) / 2;
+}
+
+fn windAt(location: vec2<i32>) -> vec2<f32> {
+  var wind = gravity.wind;
+  let square = vec2<u32>(max(location, vec2<i32>(0))) / gravity.field_cell_size;
+  if (all(square < gravity.field_size)) {
+    wind += wind_field[square.y * gravity.field_size.x + square.x];
+  }
+  return wind;
+}
+
+fn swapCells(cells: ptr<function, array<Cell, 4>>, a: i32, b: i32) {
+  let tmp = (*cells)[a];
+  (*cells)[a] = (*cells)[b];
+  (*cells)[b] = tmp;
+  (*cells)[a].flags |= moved;
+  (*cells)[b].flags |= moved;
+}
+
+// wind blows whatever it can carry into an empty cell next to it downwind,
+// lighter cells more often, `wind` is along the row from a to b
+fn blow(cells: ptr<function, array<Cell, 4>>, a: i32, b: i32, wind: f32, chance: f32) {
+  if ((((*cells)[a].flags | (*cells)[b].flags) & moved) != 0u) {
+    return;
+  }
+  let upwind = select(b, a, wind > 0.0);
+  let downwind = select(a, b, wind > 0.0);
+  let carried = (*cells)[upwind];
+  if (!isMovable(carried) || carried.material == empty || (*cells)[downwind].material != empty) {
+    return;
+  }
+  if (chance < abs(wind) / materials[carried.material].density) {
+    swapCells(cells, a, b);
+  }
+}
+
+fn blockRandom(block: vec2<u32>) -> u32 {
+  let pass_index = step.tick * 2u + step.phase;
+  return hash(step.seed ^ hash(pass_index ^ hash(block.x ^ hash(block.y))));
+}
+
+// a chunk is one workgroup of blocks, the blocks of the odd phase reach one
+// cell past the edge
+fn chunkGrid() -> vec2<u32> {
+  let blocks = textureDimensions(src) / 2u + 1u;
+  return (blocks + workgroup_size - 1u) / workgroup_size;
+}
+
+fn chunkIndex(chunk: vec2<u32>) -> u32 {
+  return chunk.y * chunkGrid().x + chunk.x;
+}
+
+// first flag of the half of `changed` that the passes of the tick write
+fn changedHalf(tick: u32) -> u32 {
+  let grid = chunkGrid();
+  return (tick % 2u) * grid.x * grid.y;
+}
+
+// wakes the chunk of a cell changed outside the update passes, in both halves
+// so that the next tick sees it whichever one it reads
+fn wakeChunk(location: vec2<i32>) {
+  let index = chunkIndex(vec2<u32>(location) / 2u / workgroup_size);
+  atomicStore(&changed[changedHalf(0u) + index], 1u);
+  atomicStore(&changed[changedHalf(1u) + index], 1u);
+}
+
+@compute @workgroup_size(1, 1, 1)
+fn resetSchedule() {
+  atomicStore(&dispatch.x, 0u);
+}
+
+// Runs once per chunk before the passes of a tick. A chunk is updated if it or
+// one of its neighbours changed in the tick before. The ring of chunks around
+// those is copied: the odd pass of an updated chunk reads the cells[1] of the
+// chunks before it, and writes back cells that the even pass of the chunk
+// after it may have moved, so both have to be up to date in cells[1].
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn schedule(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let grid = chunkGrid();
+  let chunk = invocation_id.xy;
+  if (any(chunk >= grid)) {
+    return;
+  }
+
+  let last = changedHalf(step.tick + 1u);
+  var mode = skipped;
+  for (var y = max(i32(chunk.y) - 2, 0); y <= min(i32(chunk.y) + 2, i32(grid.y) - 1); y++) {
+    for (var x = max(i32(chunk.x) - 2, 0); x <= min(i32(chunk.x) + 2, i32(grid.x) - 1); x++) {
+      if (atomicLoad(&changed[last + chunkIndex(vec2<u32>(u32(x), u32(y)))]) != 0u) {
+        let distance = max(abs(x - i32(chunk.x)), abs(y - i32(chunk.y)));
+        mode = max(mode, select(copied, updated, distance <= 1));
       }
     }
   }
+
+  // the passes of this tick start from a clean slate
+  let index = chunkIndex(chunk);
+  atomicStore(&changed[changedHalf(step.tick) + index], 0u);
+  modes[index] = mode;
+  if (mode != skipped) {
+    active_chunks[atomicAdd(&dispatch.x, 1u)] = index;
+  }
 }
 
-fn clear() {
-  for (var y = 0; y < 1280; y++) {
-    for (var x = 0; x < 1280; x++) {
-      let location = vec2<i32>(x, y);
-      textureStore(texture, location, empty);
+// What a copied chunk does instead of updating its block. The even pass copies
+// all four cells into cells[1]. The odd pass copies back only those that the
+// even pass wrote, cells[0] already holds the others.
+fn copyBlock(origin: vec2<i32>) {
+  for (var i = 0; i < 4; i++) {
+    let location = origin + vec2<i32>(i % 2, i / 2);
+    if (!inBounds(location)) {
+      continue;
+    }
+    let even_chunk = chunkIndex(vec2<u32>(location) / 2u / workgroup_size);
+    if (step.phase == 0u || modes[even_chunk] != skipped) {
+      textureStore(dst, location, textureLoad(src, location, 0));
     }
   }
 }
 
-fn drawSand() {
-  for (var y = 0; y < 1280; y++) {
-    for (var x = 0; x < 1280; x++) {
-      let location = vec2<i32>(x, y);
-      let cur_color = textureLoad(texture, location);
-      if(isEmpty(cur_color) && randomFloat(location.x * location.y * i32(time.seconds_since_startup * 1000.0)) > 0.9) {
-        textureStore(texture, location, sand);
+// true if the cell keeps its chunk awake: it moved or reacted, its temperature
+// noticeably changed, or it may still decay
+fn keepsAwake(before: Cell, after: Cell) -> bool {
+  if ((after.flags & moved) != 0u || abs(after.temperature - before.temperature) > 0.1) {
+    return true;
+  }
+  return after.material < arrayLength(&materials) && materials[after.material].decay_into != none;
+}
+
+// true if two cells side by side may trade places in a later pass, even though
+// they did not in this one: fluids spread and the wind blows things only by
+// chance, and a chunk that sleeps does not get another one
+fn mayTrade(a: Cell, b: Cell, wind: f32) -> bool {
+  if (isFluid(a) && isFluid(b) && a.material != b.material && max(materials[a.material].dispersion, materials[b.material].dispersion) > 0.0) {
+    return true;
+  }
+  let upwind = select(a, b, wind < 0.0);
+  let downwind = select(b, a, wind < 0.0);
+  return wind != 0.0 && isMovable(upwind) && upwind.material != empty && downwind.material == empty;
+}
+
+// Every invocation owns a 2x2 block of the grid (Margolus neighbourhood) and
+// only ever permutes the cells inside it, so no two invocations touch the same
+// cell and the amount of every material is conserved. The grid is shifted by
+// one cell every other pass so that material can cross block borders.
+//
+//   0 1
+//   2 3   (towards gravity)
+//
+// Returns the number of cells of the block that moved.
+fn updateBlock(chunk: u32, block: vec2<u32>, origin: vec2<i32>) -> u32 {
+    var locations = array<vec2<i32>, 4>(
+      origin + blockOffset(0),
+      origin + blockOffset(1),
+      origin + blockOffset(2),
+      origin + blockOffset(3),
+    );
+    var cells = array<Cell, 4>(
+      loadCell(locations[0]),
+      loadCell(locations[1]),
+      loadCell(locations[2]),
+      loadCell(locations[3]),
+    );
+    let loaded = cells;
+    let random = blockRandom(block);
+
+    // fall straight down
+    let left_falls = sinks(cells[0], cells[2]);
+    let right_falls = sinks(cells[1], cells[3]);
+    if (left_falls) {
+      swapCells(&cells, 0, 2);
+    }
+    if (right_falls) {
+      swapCells(&cells, 1, 3);
+    }
+
+    // slide off whatever could not be displaced below
+    var slides = false;
+    if (!left_falls && !right_falls) {
+      if (sinks(cells[0], cells[3])) {
+        swapCells(&cells, 0, 3);
+        slides = true;
+      } else if (sinks(cells[1], cells[2])) {
+        swapCells(&cells, 1, 2);
+        slides = true;
       }
     }
-  }
+
+    // resting liquids spread sideways, gases drift sideways even while they rise
+    let resting = !left_falls && !right_falls && !slides;
+    if ((resting || bothGases(cells[0], cells[1])) && spreads(cells[0], cells[1], f32(random & 0xffffu) / 65536.0)) {
+      swapCells(&cells, 0, 1);
+    }
+    if ((resting || bothGases(cells[2], cells[3])) && spreads(cells[2], cells[3], f32(random >> 16u) / 65536.0)) {
+      swapCells(&cells, 2, 3);
+    }
+
+    let wind = dot(windAt(origin), vec2<f32>(lateral()));
+    let gust = hash(random ^ 4u);
+    blow(&cells, 0, 1, wind, f32(gust & 0xffffu) / 65536.0);
+    blow(&cells, 2, 3, wind, f32(gust >> 16u) / 65536.0);
+
+    exchangeHeat(&cells);
+    var awake = false;
+    var moved_cells = 0u;
+    for (var i = 0; i < 4; i++) {
+      cells[i] = react(cells[i], hash(random ^ u32(i)));
+      if (inBounds(locations[i])) {
+        awake = awake || keepsAwake(loaded[i], cells[i]);
+        moved_cells += cells[i].flags & moved;
+      }
+      storeCell(locations[i], cells[i]);
+    }
+    if (awake || mayTrade(cells[0], cells[1], wind) || mayTrade(cells[2], cells[3], wind)) {
+      atomicStore(&changed[changedHalf(step.tick) + chunk], 1u);
+    }
+    return moved_cells;
 }
 
-fn drawWater() {
-  for (var y = 0; y < 1280; y++) {
-    for (var x = 0; x < 1280; x++) {
-      let location = vec2<i32>(x, y);
-      let cur_color = textureLoad(texture, location);
-      if(isEmpty(cur_color) && randomFloat(location.x / location.y * i32(time.seconds_since_startup * 1000.0)) > 0.9) {
-        textureStore(texture, location, water);
+// zero at the start of every workgroup
+var<workgroup> workgroup_moves: atomic<u32>;
+
+// Dispatched indirectly with one workgroup per chunk that is updated or copied.
+// Like countCells in stats.wgsl, every workgroup adds up its moves first and
+// then adds them to the buffer once.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn update(
+  @builtin(workgroup_id) workgroup_id: vec3<u32>,
+  @builtin(local_invocation_id) local_id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+    let chunk = active_chunks[workgroup_id.x];
+    let grid = chunkGrid();
+    let block = vec2<u32>(chunk % grid.x, chunk / grid.x) * workgroup_size + local_id.xy;
+    let origin = vec2<i32>(block) * 2 - vec2<i32>(i32(step.phase));
+    if (modes[chunk] == copied) {
+      copyBlock(origin);
+    } else {
+      atomicAdd(&workgroup_moves, updateBlock(chunk, block, origin));
+    }
+
+    workgroupBarrier();
+
+    if (local_index == 0u) {
+      let count = atomicLoad(&workgroup_moves);
+      if (count != 0u) {
+        atomicAdd(&moves, count);
+      }
+    }
+}
+
+// Stamps the brush along the stroke from its last position to the current
+// one. Only dispatched over the bounding box of the stroke, starting at origin.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn paint(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let location = brush.origin + vec2<i32>(invocation_id.xy);
+    let point = vec2<f32>(location) + 0.5;
+
+    let stroke = brush.end - brush.start;
+    let along = clamp(dot(point - brush.start, stroke) / max(dot(stroke, stroke), 0.0001), 0.0, 1.0);
+    let offset = abs(point - (brush.start + stroke * along));
+
+    var inside = length(offset) <= brush.radius;
+    if (brush.shape == shape_square) {
+      inside = max(offset.x, offset.y) <= brush.radius;
+    }
+    if (inside) {
+      var cell = newGrain(brush.material, location);
+      cell.flags = moved;
+      storeCell(location, cell);
+      if (inBounds(location)) {
+        wakeChunk(location);
       }
     }
+}
+
+// cells closer to a body than this count towards its support
+const body_margin = 2.0;
+// how far a displaced cell is pushed at most
+const max_push = 16;
+
+// distance from the edge of the body, negative inside
+fn bodyDistance(body: Body, point: vec2<f32>) -> f32 {
+  let offset = point - body.center;
+  let local = vec2<f32>(
+    offset.x * body.rotation.x + offset.y * body.rotation.y,
+    offset.y * body.rotation.x - offset.x * body.rotation.y,
+  );
+  if (body.shape == shape_ball) {
+    return length(local) - body.half_size.x;
   }
+  let outside = abs(local) - body.half_size;
+  return length(max(outside, vec2<f32>(0.0))) + min(max(outside.x, outside.y), 0.0);
 }
 
-fn restart(location: vec2<i32>) {
-  drawWater();
-  drawSand();
-  drawBricks();
+fn insideAnyBody(point: vec2<f32>) -> bool {
+  for (var i = 0u; i < bodies.count; i++) {
+    if (bodyDistance(bodies.bodies[i], point) <= 0.0) {
+      return true;
+    }
+  }
+  return false;
 }
 
-@compute @workgroup_size(8, 8, 1)
-fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-  let location = vec2<i32>(invocation_id.xy);
-  if(location.x == 0 && location.y == 0) {
-    restart(location);
+fn cellIndex(location: vec2<i32>) -> u32 {
+  return u32(location.y) * textureDimensions(src).x + u32(location.x);
+}
+
+// Moves the cell at `location` out of the way of a body: claims the first
+// empty cell outside all bodies on the way from the center of the body
+// through the cell. placeDisplaced does the actual move.
+fn pushAside(location: vec2<i32>, body: Body) -> bool {
+  let point = vec2<f32>(location) + 0.5;
+  var direction = point - body.center;
+  if (length(direction) < 0.001) {
+    direction = -vec2<f32>(gravity.down);
+  }
+  direction = normalize(direction);
+  for (var distance = 1; distance <= max_push; distance++) {
+    let destination = vec2<i32>(floor(point + direction * f32(distance)));
+    if (!inBounds(destination) || all(destination == location)) {
+      continue;
+    }
+    if (textureLoad(src, destination, 0).x != empty || insideAnyBody(vec2<f32>(destination) + 0.5)) {
+      continue;
+    }
+    if (atomicCompareExchangeWeak(&claims[cellIndex(destination)], 0u, cellIndex(location) + 1u).exchanged) {
+      return true;
+    }
   }
+  return false;
 }
 
-fn inBounds(location: vec2<i32>) -> bool {
-  return location.x < 1280 && location.x >= 0 && location.y < 1280 && location.y >= 0;
-}
-
-fn sandNextLocation(cur_location: vec2<i32>) -> vec2<i32> {
-  let below = vec2<i32>(cur_location.x, cur_location.y + 1);
-  let below_right = vec2<i32>(cur_location.x + 1, cur_location.y + 1);
-  let below_left = vec2<i32>(cur_location.x - 1, cur_location.y + 1);
-  let color_below = textureLoad(texture, below);
-  let color_below_right = textureLoad(texture, below_right);
-  let color_below_left = textureLoad(texture, below_left);
-  if (isEmptyOrWater(color_below) && inBounds(below)) {
-    return below;
-  } else if (isEmptyOrWater(color_below_right) && inBounds(below_right)) {
-    return below_right;
-  } else if (isEmptyOrWater(color_below_left) && inBounds(below_left)) {
-    return below_left;
-  }
-  return cur_location;
-}
-
-fn waterNextLocation(cur_location: vec2<i32>) -> vec2<i32> {
-  let below = vec2<i32>(cur_location.x, cur_location.y + 1);
-  let below_right = vec2<i32>(cur_location.x + 1, cur_location.y + 1);
-  let below_left = vec2<i32>(cur_location.x - 1, cur_location.y + 1);
-  let right = vec2<i32>(cur_location.x + 1, cur_location.y);
-  let left = vec2<i32>(cur_location.x - 1, cur_location.y);
-  let color_below = textureLoad(texture, below);
-  let color_below_right = textureLoad(texture, below_right);
-  let color_below_left = textureLoad(texture, below_left);
-  let color_right = textureLoad(texture, right);
-  let color_left = textureLoad(texture, left);
-  if (isEmpty(color_below) && inBounds(below)) {
-    return below;
-  } else if (isEmpty(color_below_right) && inBounds(below_right)) {
-    return below_right;
-  } else if (isEmpty(color_below_left) && inBounds(below_left)) {
-    return below_left;
-  } else if (isEmpty(color_right) && inBounds(right)) {
-    return right;
-  } else if (isEmpty(color_left) && inBounds(left)) {
-    return left;
-  }
-  return cur_location;
-}
-
-const epsilon = 0.001;
-fn isWater(color: vec4<f32>) -> bool {
-  return distance(color, water) < epsilon;
-}
-fn isSand(color: vec4<f32>) -> bool {
-  return distance(color, sand) < epsilon;
-}
-fn isBrick(color: vec4<f32>) -> bool {
-  return distance(color, brick) < epsilon;
-}
-fn isEmpty(color: vec4<f32>) -> bool {
-  return distance(color, empty) < epsilon;
-}
-fn isEmptyOrWater(color: vec4<f32>) -> bool {
-  return isEmpty(color) || isWater(color);
-}
-
-fn locationOfNextEmptyUpwards(location: vec2<i32>) -> vec2<i32> {
-  for (var y = location.y; y >= 0; y--) {
-    let loc = vec2<i32>(location.x, y);
-    let color = textureLoad(texture, loc);
-    if(isEmpty(color)) {
-      return loc;
-    }
-  }
-  return vec2<i32>(location.x, 0);
-}
-
-@compute @workgroup_size(8, 8, 1)
-fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let location = vec2<i32>(invocation_id.xy);
-
-    let cur_color = textureLoad(texture, location);
-
-    var new_location = location;
-    if (isSand(cur_color)) {
-      new_location = sandNextLocation(location);
-    } else if (isWater(cur_color)) {
-      new_location = waterNextLocation(location);
-    }
-
-    if (any(location != new_location)) {
-      workgroupBarrier();
-      let color_to_be_replaced = textureLoad(texture, new_location);
-      textureStore(texture, location, color_to_be_replaced);
-      textureStore(texture, new_location, cur_color);
-    } 
+// zero at the start of every workgroup, summed up like in stats.wgsl
+var<workgroup> local_support: array<atomic<u32>, #{BODY_SLOTS}>;
+
+// liquids and powders around a body hold it up and slow it down
+fn addSupport(body: u32, cell: Cell) {
+  atomicAdd(&local_support[body * 3u], 1u);
+  if (cell.material >= arrayLength(&materials) || cell.material == bodies.material) {
+    return;
+  }
+  let material = materials[cell.material];
+  if (material.kind == kind_liquid || material.kind == kind_powder) {
+    atomicAdd(&local_support[body * 3u + 1u], 1u);
+    atomicAdd(&local_support[body * 3u + 2u], u32(material.density * 256.0));
+  }
+}
+
+fn stampCell(location: vec2<i32>) {
+  let point = vec2<f32>(location) + 0.5;
+  let texel = textureLoad(src, location, 0);
+  let cell = Cell(texel.x, texel.y, bitcast<f32>(texel.z), texel.w);
+
+  var inside = -1;
+  for (var i = 0u; i < bodies.count; i++) {
+    let distance = bodyDistance(bodies.bodies[i], point);
+    if (distance <= 0.0) {
+      inside = i32(i);
+    } else if (distance <= body_margin) {
+      addSupport(i, cell);
+    }
+  }
+
+  var result = cell;
+  if (inside < 0) {
+    // the body moved on
+    if (cell.material == bodies.material) {
+      result = newCell(empty);
+    }
+  } else if (cell.material == empty || (isMovable(cell) && cell.material != bodies.material && pushAside(location, bodies.bodies[inside]))) {
+    result = newGrain(bodies.material, location);
+  }
+  // static cells stay where they are, bodies pass behind them
+
+  if (result.material != cell.material) {
+    result.flags |= moved;
+    wakeChunk(location);
+  }
+  writeCell(location, result);
+}
+
+// Draws the bodies into the grid, from cells[0] into cells[1], and sums up
+// what is around every body for the forces in bodies.rs.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn stampBodies(@builtin(global_invocation_id) invocation_id: vec3<u32>, @builtin(local_invocation_index) local_index: u32) {
+  if (all(invocation_id.xy < textureDimensions(src))) {
+    stampCell(vec2<i32>(invocation_id.xy));
+  }
+
+  workgroupBarrier();
+
+  for (var i = local_index; i < bodies.count * 3u; i += invocations) {
+    let value = atomicLoad(&local_support[i]);
+    if (value != 0u) {
+      atomicAdd(&body_support[i], value);
+    }
+  }
+}
+
+// Runs after stampBodies and moves every pushed cell to the cell it claimed,
+// which stampBodies left empty.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn placeDisplaced(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+  let location = vec2<i32>(invocation_id.xy);
+  if (!inBounds(location)) {
+    return;
+  }
+  let claim = atomicLoad(&claims[cellIndex(location)]);
+  if (claim == 0u) {
+    return;
+  }
+  let width = textureDimensions(src).x;
+  let source = vec2<i32>(i32((claim - 1u) % width), i32((claim - 1u) / width));
+  let texel = textureLoad(src, source, 0);
+  textureStore(dst, location, vec4<u32>(texel.x, texel.y | moved, texel.z, texel.w));
+  wakeChunk(location);
 }
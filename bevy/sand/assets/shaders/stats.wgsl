@@ -0,0 +1,37 @@
+@group(0) @binding(0)
+var cells: texture_2d<u32>;
+
+// [0] cells moved, copied in from the update passes, [1 + id] cells of
+// material id, see stats.rs
+@group(0) @binding(1)
+var<storage, read_write> stats: array<atomic<u32>>;
+
+const slots = #{STATS_SLOTS}u;
+const invocations = #{WORKGROUP_SIZE}u * #{WORKGROUP_SIZE}u;
+
+// zero at the start of every workgroup
+var<workgroup> local_stats: array<atomic<u32>, #{STATS_SLOTS}>;
+
+// Every workgroup counts its cells in workgroup memory first and then adds
+// its totals to the buffer, so the buffer only sees one atomic per slot and
+// workgroup instead of one per cell.
+@compute @workgroup_size(#{WORKGROUP_SIZE}, #{WORKGROUP_SIZE}, 1)
+fn countCells(
+  @builtin(global_invocation_id) invocation_id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  if (all(invocation_id.xy < textureDimensions(cells))) {
+    let cell = textureLoad(cells, vec2<i32>(invocation_id.xy), 0);
+    // materials past the end of the buffer share the last slot
+    atomicAdd(&local_stats[min(cell.x, slots - 2u) + 1u], 1u);
+  }
+
+  workgroupBarrier();
+
+  for (var i = local_index; i < slots; i += invocations) {
+    let count = atomicLoad(&local_stats[i]);
+    if (count != 0u) {
+      atomicAdd(&stats[i], count);
+    }
+  }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use sand::{config::ConfigArgs, run_headless, HeadlessSettings, SandConfig};
+
+// On a machine without a GPU, point wgpu at a software Vulkan driver such as
+// lavapipe with WGPU_BACKEND=vulkan.
+#[derive(Parser)]
+#[command(about = "Runs the sand simulation without a window and writes frames to PNG")]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// ticks to simulate, rounded up to a multiple of --every
+    #[arg(long)]
+    ticks: u32,
+    /// write a frame every this many ticks
+    #[arg(long, default_value_t = 1)]
+    every: u32,
+    /// directory the frames are written to
+    #[arg(long, default_value = "frames")]
+    output: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    anyhow::ensure!(cli.every > 0, "--every must be at least 1");
+    let config = SandConfig::from_config_args(&cli.config)?;
+    run_headless(
+        config,
+        HeadlessSettings {
+            ticks: cli.ticks,
+            every: cli.every,
+            output: cli.output,
+        },
+    )
+}
@@ -4,35 +4,162 @@ use bevy::{
 };
 
 use crate::{
-    image::SandImage,
-    pipeline::SandPipeline, time::TimeMeta,
+    bodies::BodyBuffers, brush::BrushBuffer, cells::SandCells, chunks::SandChunks,
+    gravity::GravityBuffer, image::SandImage, material::MaterialBuffer, palette::PaletteBuffer,
+    pipeline::SandPipeline, scene::SceneBuffer, stats::StatsReadback, step::SandSteps,
+    time::TimeMeta,
 };
 
 #[derive(Resource)]
-pub struct SandBindGroup(pub BindGroup);
+pub struct SandBindGroups {
+    // [0] reads cells[0] and writes cells[1], [1] the other way round
+    pub update: [BindGroup; 2],
+    pub draw: BindGroup,
+    // group 1 of stampBodies and placeDisplaced
+    pub bodies: BindGroup,
+    // group 1 of resetSchedule and schedule
+    pub schedule: BindGroup,
+}
 
-pub fn queue_bind_group(
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_bind_group(
     mut commands: Commands,
     pipeline: Res<SandPipeline>,
     gpu_images: Res<RenderAssets<Image>>,
-    hello_image: Res<SandImage>,
+    sand_image: Res<SandImage>,
+    cells: Res<SandCells>,
+    chunks: Res<SandChunks>,
     render_device: Res<RenderDevice>,
-    time_meta: ResMut<TimeMeta>,
+    time_meta: Res<TimeMeta>,
+    steps: Res<SandSteps>,
+    material_buffer: Res<MaterialBuffer>,
+    palette_buffer: Res<PaletteBuffer>,
+    brush_buffer: Res<BrushBuffer>,
+    gravity_buffer: Res<GravityBuffer>,
+    body_buffers: Res<BodyBuffers>,
+    stats_readback: Res<StatsReadback>,
+    scene_buffer: Res<SceneBuffer>,
 ) {
-    let view = &gpu_images[&hello_image.0];
-    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
-        label: None,
-        layout: &pipeline.texture_bind_group_layout,
-        entries: &[
+    let view = &gpu_images.get(&sand_image.0).unwrap().texture_view;
+    let step_binding = steps.buffer.binding().unwrap();
+    let material_binding = material_buffer.0.binding().unwrap();
+    let brush_binding = brush_buffer.0.binding().unwrap();
+    let gravity_binding = gravity_buffer.uniform.binding().unwrap();
+    let wind_field_binding = gravity_buffer.field.binding().unwrap();
+    let scene_binding = scene_buffer.0.binding().unwrap();
+
+    let update = [0, 1].map(|i| {
+        render_device.create_bind_group(
+            None,
+            &pipeline.texture_bind_group_layout,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&cells.views[i]),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&cells.views[1 - i]),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: time_meta.buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: step_binding.clone(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: material_binding.clone(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: brush_binding.clone(),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: chunks.changed.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: chunks.active.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: chunks.modes.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: gravity_binding.clone(),
+                },
+                BindGroupEntry {
+                    binding: 10,
+                    resource: wind_field_binding.clone(),
+                },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: stats_readback.moves.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 12,
+                    resource: scene_binding.clone(),
+                },
+            ],
+        )
+    });
+    let draw = render_device.create_bind_group(
+        None,
+        &pipeline.draw_bind_group_layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&cells.views[0]),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: palette_buffer.0.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: chunks.changed.as_entire_binding(),
+            },
+        ],
+    );
+    let bodies = render_device.create_bind_group(
+        None,
+        &pipeline.bodies_bind_group_layout,
+        &[
             BindGroupEntry {
                 binding: 0,
-                resource: BindingResource::TextureView(&view.texture_view),
+                resource: body_buffers.bodies.binding().unwrap(),
             },
             BindGroupEntry {
                 binding: 1,
-                resource: time_meta.buffer.as_entire_binding(),
+                resource: body_buffers.claims.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: body_buffers.support.as_entire_binding(),
             },
         ],
+    );
+    let schedule = render_device.create_bind_group(
+        None,
+        &pipeline.schedule_bind_group_layout,
+        &[BindGroupEntry {
+            binding: 3,
+            resource: chunks.dispatch.as_entire_binding(),
+        }],
+    );
+    commands.insert_resource(SandBindGroups {
+        update,
+        draw,
+        bodies,
+        schedule,
     });
-    commands.insert_resource(SandBindGroup(bind_group));
 }
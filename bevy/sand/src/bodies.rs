@@ -0,0 +1,441 @@
+use std::{
+    f32::consts::PI,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bevy::{
+    prelude::*,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+};
+use bevy_rapier2d::prelude::*;
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{
+    camera::{world_to_cell, Cursor},
+    config::SandConfig,
+    gravity::GravitySettings,
+    material::MaterialRegistry,
+};
+
+// bodies past this many are not drawn into the grid
+pub const MAX_BODIES: u32 = 64;
+// three numbers per body, see `body_support` in sand.wgsl
+pub const BODY_SLOTS: u32 = MAX_BODIES * 3;
+const SUPPORT_BYTES: u64 = BODY_SLOTS as u64 * 4;
+
+// one cell is one pixel of the physics world
+pub const PIXELS_PER_METER: f32 = 10.0;
+// how quickly what a body is in slows it down, per second
+const DRAG: f32 = 2.0;
+
+const BALL_RADIUS: f32 = 16.0;
+const CRATE_HALF_SIZE: f32 = 20.0;
+// in the units of the material densities, water is 2
+const BALL_DENSITY: f32 = 2.5;
+const CRATE_DENSITY: f32 = 1.2;
+
+// A rapier body that is drawn into the grid as the `body` material every
+// frame. It pushes aside whatever it lands on, and the liquids and powders
+// around it hold it up and slow it down. Only balls and cuboids are drawn.
+#[derive(Component)]
+pub struct SandBody;
+
+// in the order of the shapes in sand.wgsl
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BodyShape {
+    Ball,
+    Box,
+}
+
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct GpuBody {
+    // in cell coordinates
+    pub center: Vec2,
+    pub half_size: Vec2,
+    pub rotation: Vec2,
+    pub shape: u32,
+}
+
+#[derive(Clone, Default, ShaderType)]
+pub struct GpuBodies {
+    pub count: u32,
+    pub material: u32,
+    #[size(runtime)]
+    pub bodies: Vec<GpuBody>,
+}
+
+// the bodies as they are drawn this frame
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct SandBodies {
+    pub entities: Vec<Entity>,
+    pub bodies: Vec<GpuBody>,
+    // None until the registry with the body material is loaded
+    pub material: Option<u32>,
+}
+
+#[derive(Resource)]
+pub struct BodySupportReceiver(pub Receiver<(Vec<Entity>, Vec<u32>)>);
+
+fn shape_of(collider: &Collider) -> Option<(BodyShape, Vec2)> {
+    if let Some(ball) = collider.as_ball() {
+        Some((BodyShape::Ball, Vec2::splat(ball.radius())))
+    } else {
+        collider
+            .as_cuboid()
+            .map(|cuboid| (BodyShape::Box, cuboid.half_extents()))
+    }
+}
+
+fn area(shape: BodyShape, half_size: Vec2) -> f32 {
+    match shape {
+        BodyShape::Ball => PI * half_size.x * half_size.x,
+        BodyShape::Box => 4.0 * half_size.x * half_size.y,
+    }
+}
+
+// keeps the bodies inside the world
+pub fn spawn_walls(mut commands: Commands, config: Res<SandConfig>) {
+    let half = Vec2::new(config.size.0 as f32, config.size.1 as f32) / 2.0;
+    let thickness = 50.0;
+    for (center, half_size) in [
+        (
+            Vec2::new(0.0, -half.y - thickness),
+            Vec2::new(half.x, thickness),
+        ),
+        (
+            Vec2::new(0.0, half.y + thickness),
+            Vec2::new(half.x, thickness),
+        ),
+        (
+            Vec2::new(-half.x - thickness, 0.0),
+            Vec2::new(thickness, half.y),
+        ),
+        (
+            Vec2::new(half.x + thickness, 0.0),
+            Vec2::new(thickness, half.y),
+        ),
+    ] {
+        commands.spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(half_size.x, half_size.y),
+            TransformBundle::from(Transform::from_translation(center.extend(0.0))),
+        ));
+    }
+}
+
+fn body_bundle(shape: BodyShape, position: Vec2) -> impl Bundle {
+    let (collider, density) = match shape {
+        BodyShape::Ball => (Collider::ball(BALL_RADIUS), BALL_DENSITY),
+        BodyShape::Box => (
+            Collider::cuboid(CRATE_HALF_SIZE, CRATE_HALF_SIZE),
+            CRATE_DENSITY,
+        ),
+    };
+    (
+        SandBody,
+        RigidBody::Dynamic,
+        collider,
+        ColliderMassProperties::Density(density),
+        Velocity::default(),
+        ExternalForce::default(),
+        TransformBundle::from(Transform::from_translation(position.extend(0.0))),
+    )
+}
+
+pub fn spawn_body(commands: &mut Commands, shape: BodyShape, position: Vec2) {
+    commands.spawn(body_bundle(shape, position));
+}
+
+// b drops a ball and x a crate at the cursor
+pub fn spawn_bodies(mut commands: Commands, keys: Res<Input<KeyCode>>, cursor: Cursor) {
+    let Some(position) = cursor.world_position() else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::B) {
+        spawn_body(&mut commands, BodyShape::Ball, position);
+    }
+    if keys.just_pressed(KeyCode::X) {
+        spawn_body(&mut commands, BodyShape::Box, position);
+    }
+}
+
+// bodies fall the same way as the cells do
+pub fn sync_physics_gravity(
+    gravity: Res<GravitySettings>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if gravity.is_changed() {
+        let down = gravity.direction.down().as_vec2();
+        rapier_config.gravity = Vec2::new(down.x, -down.y) * rapier_config.gravity.length();
+    }
+}
+
+pub fn collect_bodies(
+    mut sand_bodies: ResMut<SandBodies>,
+    bodies: Query<(Entity, &Transform, &Collider), With<SandBody>>,
+    registry: Option<Res<MaterialRegistry>>,
+    config: Res<SandConfig>,
+    mut warned: Local<bool>,
+) {
+    sand_bodies.entities.clear();
+    sand_bodies.bodies.clear();
+    sand_bodies.material = registry.and_then(|registry| registry.id("body"));
+
+    for (entity, transform, collider) in &bodies {
+        let Some((shape, half_size)) = shape_of(collider) else {
+            continue;
+        };
+        if sand_bodies.bodies.len() == MAX_BODIES as usize {
+            if !*warned {
+                warn!("only the first {MAX_BODIES} bodies are drawn into the grid");
+                *warned = true;
+            }
+            break;
+        }
+        // y points the other way in the grid, and so do the angles
+        let (angle, _, _) = transform.rotation.to_euler(EulerRot::ZYX);
+        sand_bodies.entities.push(entity);
+        sand_bodies.bodies.push(GpuBody {
+            center: world_to_cell(transform.translation.truncate(), &config),
+            half_size,
+            rotation: Vec2::new(angle.cos(), -angle.sin()),
+            shape: shape as u32,
+        });
+    }
+}
+
+// Buoyancy and drag on a body with `fraction` of it in cells of `density`. A
+// body half in water is held up by the weight of water it would take to fill
+// half of it. Rapier takes the mass of a collider as its density times its area
+// in square meters, so the displaced mass has to be too.
+fn fluid_force(
+    shape: BodyShape,
+    half_size: Vec2,
+    fraction: f32,
+    density: f32,
+    gravity: Vec2,
+    velocity: &Velocity,
+) -> ExternalForce {
+    let area = area(shape, half_size) / (PIXELS_PER_METER * PIXELS_PER_METER);
+    let displaced = area * fraction * density;
+    ExternalForce {
+        force: -gravity * displaced - velocity.linvel * displaced * DRAG,
+        torque: -velocity.angvel * displaced * area / PI * DRAG,
+    }
+}
+
+// Turns what the GPU found around every body into buoyancy and drag.
+pub fn apply_body_forces(
+    receiver: Res<BodySupportReceiver>,
+    rapier_config: Res<RapierConfiguration>,
+    mut bodies: Query<(&Collider, &Velocity, &mut ExternalForce), With<SandBody>>,
+) {
+    let Some((entities, support)) = receiver.0.try_iter().last() else {
+        return;
+    };
+    for (entity, support) in entities.iter().zip(support.chunks_exact(3)) {
+        let Ok((collider, velocity, mut force)) = bodies.get_mut(*entity) else {
+            continue;
+        };
+        let Some((shape, half_size)) = shape_of(collider) else {
+            continue;
+        };
+        let [around, holding, density] = [support[0], support[1], support[2]];
+        if around == 0 || holding == 0 {
+            *force = ExternalForce::default();
+            continue;
+        }
+        let fraction = holding as f32 / around as f32;
+        let density = density as f32 / 256.0 / holding as f32;
+        *force = fluid_force(
+            shape,
+            half_size,
+            fraction,
+            density,
+            rapier_config.gravity,
+            velocity,
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct BodyBuffers {
+    pub bodies: StorageBuffer<GpuBodies>,
+    pub claims: Buffer,
+    pub support: Buffer,
+    // a copy of `support` that gets mapped
+    staging: Buffer,
+    // whether the node draws the bodies this frame
+    pub active: bool,
+    // the bodies drawn this frame, in the order of their support
+    entities: Vec<Entity>,
+    // set by the node when it copied the support into `staging`
+    pub copied: AtomicBool,
+    pending: Option<(Vec<Entity>, Receiver<bool>)>,
+    sender: Sender<(Vec<Entity>, Vec<u32>)>,
+}
+
+impl BodyBuffers {
+    pub fn staging(&self) -> &Buffer {
+        &self.staging
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+pub fn create_body_buffers(
+    render_device: &RenderDevice,
+    config: &SandConfig,
+    sender: Sender<(Vec<Entity>, Vec<u32>)>,
+) -> BodyBuffers {
+    let claims = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_body_claims"),
+        size: (config.size.0 * config.size.1) as u64 * 4,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let support = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_body_support"),
+        size: SUPPORT_BYTES,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let staging = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_body_support_readback"),
+        size: SUPPORT_BYTES,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    BodyBuffers {
+        bodies: StorageBuffer::default(),
+        claims,
+        support,
+        staging,
+        active: false,
+        entities: Vec::new(),
+        copied: AtomicBool::new(false),
+        pending: None,
+        sender,
+    }
+}
+
+pub fn prepare_bodies(
+    sand_bodies: Res<SandBodies>,
+    mut buffers: ResMut<BodyBuffers>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut had_bodies: Local<bool>,
+) {
+    // one more frame after the last body is gone to clear it from the grid
+    let has_bodies = !sand_bodies.bodies.is_empty();
+    buffers.active = sand_bodies.material.is_some() && (has_bodies || *had_bodies);
+    *had_bodies = has_bodies;
+
+    // the buffer can not be empty, padding with a body that is never looked at
+    let mut bodies = sand_bodies.bodies.clone();
+    if bodies.is_empty() {
+        bodies.push(GpuBody::default());
+    }
+    buffers.bodies.set(GpuBodies {
+        count: sand_bodies.bodies.len() as u32,
+        material: sand_bodies.material.unwrap_or(u32::MAX),
+        bodies,
+    });
+    buffers.bodies.write_buffer(&render_device, &render_queue);
+    buffers.entities.clone_from(&sand_bodies.entities);
+}
+
+// like map_stats_readback
+pub fn map_body_support(mut buffers: ResMut<BodyBuffers>) {
+    if let Some((entities, mapped)) = &buffers.pending {
+        let Ok(success) = mapped.try_recv() else {
+            return;
+        };
+        if success {
+            let support = buffers
+                .staging
+                .slice(..)
+                .get_mapped_range()
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            let _ = buffers.sender.send((entities.clone(), support));
+        } else {
+            error!("could not map the body support buffer");
+        }
+        buffers.staging.unmap();
+        buffers.pending = None;
+    } else if buffers.copied.swap(false, Ordering::Relaxed) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        buffers
+            .staging
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result.is_ok());
+            });
+        buffers.pending = Some((buffers.entities.clone(), receiver));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::transform::TransformPlugin;
+
+    use super::*;
+
+    // the ball is denser than water and the crate lighter
+    const WATER_DENSITY: f32 = 2.0;
+
+    // what apply_body_forces does for a body that is all under water
+    fn submerge(
+        rapier_config: Res<RapierConfiguration>,
+        mut bodies: Query<(&Collider, &Velocity, &mut ExternalForce)>,
+    ) {
+        for (collider, velocity, mut force) in &mut bodies {
+            let (shape, half_size) = shape_of(collider).unwrap();
+            *force = fluid_force(
+                shape,
+                half_size,
+                1.0,
+                WATER_DENSITY,
+                rapier_config.gravity,
+                velocity,
+            );
+        }
+    }
+
+    // where a body starting at rest under water is after two seconds
+    fn height_after_sinking(shape: BodyShape) -> f32 {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(PIXELS_PER_METER),
+        ))
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: 1.0 / 60.0,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .add_systems(Update, submerge);
+        let body = app.world.spawn(body_bundle(shape, Vec2::ZERO)).id();
+        for _ in 0..120 {
+            app.update();
+        }
+        app.world.get::<Transform>(body).unwrap().translation.y
+    }
+
+    #[test]
+    fn denser_bodies_sink() {
+        assert!(height_after_sinking(BodyShape::Ball) < -10.0);
+    }
+
+    #[test]
+    fn lighter_bodies_float() {
+        assert!(height_after_sinking(BodyShape::Box) > 10.0);
+    }
+}
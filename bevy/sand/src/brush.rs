@@ -0,0 +1,153 @@
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+};
+
+use crate::{
+    camera::{world_to_cell, Cursor},
+    config::SandConfig,
+    material::MaterialRegistry,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+}
+
+#[derive(Resource, Clone, ExtractResource)]
+pub struct BrushState {
+    pub material: u32,
+    pub radius: u32,
+    pub shape: BrushShape,
+    // cursor in cell coordinates, this frame and the previous frame of the stroke
+    pub position: Option<Vec2>,
+    pub last_position: Option<Vec2>,
+    // material stamped this frame, if a mouse button is down
+    pub stroke: Option<u32>,
+}
+
+impl Default for BrushState {
+    fn default() -> Self {
+        BrushState {
+            material: 1,
+            radius: 10,
+            shape: BrushShape::Circle,
+            position: None,
+            last_position: None,
+            stroke: None,
+        }
+    }
+}
+
+impl BrushState {
+    // origin and size of the cells the current stroke can touch
+    pub fn bounds(&self) -> Option<(IVec2, UVec2)> {
+        self.stroke?;
+        let to = self.position?;
+        let from = self.last_position.unwrap_or(to);
+        let reach = Vec2::splat(self.radius as f32 + 1.0);
+        let min = (from.min(to) - reach).floor().as_ivec2();
+        let max = (from.max(to) + reach).ceil().as_ivec2();
+        Some((min, (max - min).as_uvec2()))
+    }
+}
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+// left mouse paints, right mouse erases, shift and the wheel set the radius,
+// digits pick the material id and tab switches between circle and square
+pub fn update_brush(
+    mut brush: ResMut<BrushState>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    cursor: Cursor,
+    registry: Option<Res<MaterialRegistry>>,
+    config: Res<SandConfig>,
+) {
+    // the wheel alone zooms the camera
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for event in wheel.read() {
+        if shift {
+            let radius = brush.radius as f32 + event.y.signum();
+            brush.radius = radius.clamp(1.0, 200.0) as u32;
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Tab) {
+        brush.shape = match brush.shape {
+            BrushShape::Circle => BrushShape::Square,
+            BrushShape::Square => BrushShape::Circle,
+        };
+    }
+
+    if let Some(registry) = registry {
+        for (id, key) in DIGITS.iter().enumerate() {
+            if keys.just_pressed(*key) && id < registry.materials.len() {
+                brush.material = id as u32;
+                info!("brush material: {}", registry.materials[id].name);
+            }
+        }
+    }
+
+    brush.last_position = brush.stroke.and(brush.position);
+    // there is no window to paint in when running headless
+    brush.position = cursor
+        .world_position()
+        .map(|position| world_to_cell(position, &config));
+    brush.stroke = if buttons.pressed(MouseButton::Left) {
+        Some(brush.material)
+    } else if buttons.pressed(MouseButton::Right) {
+        Some(0)
+    } else {
+        None
+    };
+}
+
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct GpuBrush {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub origin: IVec2,
+    pub material: u32,
+    pub radius: f32,
+    pub shape: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct BrushBuffer(pub UniformBuffer<GpuBrush>);
+
+pub fn prepare_brush(
+    brush: Res<BrushState>,
+    mut brush_buffer: ResMut<BrushBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if let (Some(material), Some(end), Some((origin, _))) =
+        (brush.stroke, brush.position, brush.bounds())
+    {
+        brush_buffer.0.set(GpuBrush {
+            start: brush.last_position.unwrap_or(end),
+            end,
+            origin,
+            material,
+            radius: brush.radius as f32,
+            shape: brush.shape as u32,
+        });
+    }
+    brush_buffer.0.write_buffer(&render_device, &render_queue);
+}
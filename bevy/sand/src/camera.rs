@@ -1,5 +1,162 @@
-use bevy::prelude::Component;
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    ecs::system::SystemParam,
+    input::mouse::MouseWheel,
+    prelude::*,
+    render::{camera::*, view::RenderLayers},
+    window::PrimaryWindow,
+};
+
+use crate::config::SandConfig;
 
 #[derive(Component)]
 pub struct MainCamera;
 
+// shows the whole world in a corner of the window
+#[derive(Component)]
+pub struct MinimapCamera;
+
+// the gizmos are only drawn on the minimap
+pub const MINIMAP_LAYER: u8 = 1;
+// longest side of the minimap and its distance to the window edges, in
+// logical pixels
+const MINIMAP_SIZE: f32 = 200.0;
+const MINIMAP_MARGIN: f32 = 8.0;
+
+// world units per logical pixel when zoomed in all the way
+const MIN_SCALE: f32 = 1.0 / 16.0;
+const ZOOM_STEP: f32 = 1.25;
+
+// The sand sprite is centered on the origin, one cell per world unit with y
+// up. Cells are counted from its top left corner with y down.
+pub fn world_to_cell(position: Vec2, config: &SandConfig) -> Vec2 {
+    Vec2::new(
+        position.x + config.size.0 as f32 / 2.0,
+        config.size.1 as f32 / 2.0 - position.y,
+    )
+}
+
+#[derive(SystemParam)]
+pub struct Cursor<'w, 's> {
+    q_window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    q_camera: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<MainCamera>>,
+}
+
+impl Cursor<'_, '_> {
+    // the point of the world under the cursor, as the main camera sees it
+    pub fn world_position(&self) -> Option<Vec2> {
+        let cursor = self.q_window.get_single().ok()?.cursor_position()?;
+        let (camera, transform) = self.q_camera.get_single().ok()?;
+        camera.viewport_to_world_2d(transform, cursor)
+    }
+}
+
+pub fn setup_camera(
+    mut commands: Commands,
+    mut gizmo_config: ResMut<GizmoConfig>,
+    config: Res<SandConfig>,
+) {
+    commands.spawn((Camera2dBundle::default(), MainCamera));
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            // clearing would clear the whole window, not just the minimap
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::Fixed {
+                    width: config.size.0 as f32,
+                    height: config.size.1 as f32,
+                },
+                ..default()
+            },
+            ..default()
+        },
+        UiCameraConfig { show_ui: false },
+        RenderLayers::from_layers(&[0, MINIMAP_LAYER]),
+        MinimapCamera,
+    ));
+    gizmo_config.render_layers = RenderLayers::layer(MINIMAP_LAYER);
+}
+
+// the wheel zooms around the cursor and dragging with the middle mouse button
+// pans, as long as the camera stays over the world
+pub fn update_camera(
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut last_cursor: Local<Option<Vec2>>,
+    config: Res<SandConfig>,
+) {
+    let (Ok((mut transform, mut projection)), Ok(window)) =
+        (q_camera.get_single_mut(), q_window.get_single())
+    else {
+        return;
+    };
+    let world = Vec2::new(config.size.0 as f32, config.size.1 as f32);
+    let window_size = Vec2::new(window.width(), window.height());
+    let cursor = window.cursor_position();
+
+    // shift and the wheel change the brush instead
+    let zoom: f32 = wheel.read().map(|event| -event.y.signum()).sum();
+    if zoom != 0.0 && !keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        // zoomed out all the way, the world just fits into the window
+        let max_scale = (world / window_size).max_element().max(1.0);
+        let scale = (projection.scale * ZOOM_STEP.powf(zoom)).clamp(MIN_SCALE, max_scale);
+        // the cursor from the middle of the window, y up
+        let offset = cursor.map_or(Vec2::ZERO, |cursor| {
+            (cursor - window_size / 2.0) * Vec2::new(1.0, -1.0)
+        });
+        let anchor = transform.translation.truncate() + offset * projection.scale;
+        transform.translation = (anchor - offset * scale).extend(transform.translation.z);
+        projection.scale = scale;
+    }
+
+    if let (true, Some(cursor), Some(last)) =
+        (buttons.pressed(MouseButton::Middle), cursor, *last_cursor)
+    {
+        let delta = (cursor - last) * Vec2::new(-1.0, 1.0) * projection.scale;
+        transform.translation += delta.extend(0.0);
+    }
+    *last_cursor = cursor;
+
+    let half = world / 2.0;
+    let center = transform.translation.truncate().clamp(-half, half);
+    transform.translation = center.extend(transform.translation.z);
+}
+
+// keeps the minimap in the top right corner of the window, and hides it
+// when the window is too small for it
+pub fn update_minimap(
+    mut q_minimap: Query<&mut Camera, With<MinimapCamera>>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    config: Res<SandConfig>,
+) {
+    let (Ok(mut camera), Ok(window)) = (q_minimap.get_single_mut(), q_window.get_single()) else {
+        return;
+    };
+    let world = Vec2::new(config.size.0 as f32, config.size.1 as f32);
+    let scale_factor = window.scale_factor() as f32;
+    let size = (world / world.max_element() * MINIMAP_SIZE * scale_factor).max(Vec2::ONE);
+    let margin = MINIMAP_MARGIN * scale_factor;
+    let window_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+
+    let fits = (size + 2.0 * margin).cmple(window_size).all();
+    camera.is_active = fits;
+    if fits {
+        camera.viewport = Some(Viewport {
+            physical_position: UVec2::new((window_size.x - size.x - margin) as u32, margin as u32),
+            physical_size: size.as_uvec2(),
+            ..default()
+        });
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets, render_graph, render_resource::*, renderer::*, Render,
+        RenderApp, RenderSet,
+    },
+};
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{
+    config::SandConfig,
+    cpu::{run_cpu_sim, CpuSim, SandBackend},
+    image::SandImage,
+    step::SandSteps,
+};
+
+// SandImage as it was drawn at the end of a frame
+pub struct CapturedFrame {
+    // ticks simulated up to and including the frame
+    pub tick: u32,
+    pub width: u32,
+    pub height: u32,
+    // rgba8, row by row
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Resource)]
+pub struct CaptureReceiver(pub Receiver<CapturedFrame>);
+
+// Reads back every frame drawn since the world was initialized. Only meant for
+// run_headless: read_frame_capture blocks the render world on
+// `poll(Maintain::Wait)` at the end of every frame, fine for batch runs but it
+// would hold up every frame of a window. Nothing outside the crate can add it.
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, _app: &mut App) {}
+
+    fn finish(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        app.insert_resource(CaptureReceiver(receiver));
+        // SandPlugin::finish has picked the backend by now
+        if *app.world.resource::<SandBackend>() == SandBackend::Cpu {
+            app.insert_resource(CpuCapture(sender))
+                .add_systems(Update, capture_cpu_frame.after(run_cpu_sim));
+            return;
+        }
+
+        let size = app.world.resource::<SandConfig>().size;
+        let padded_bytes_per_row =
+            RenderDevice::align_copy_bytes_per_row(size.0 as usize * 4) as u32;
+        let buffer = app
+            .world
+            .resource::<RenderDevice>()
+            .create_buffer(&BufferDescriptor {
+                label: Some("sand_capture"),
+                size: (padded_bytes_per_row * size.1) as u64,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(FrameCapture {
+                buffer,
+                size,
+                padded_bytes_per_row,
+                sender,
+            })
+            .add_systems(Render, read_frame_capture.in_set(RenderSet::Cleanup));
+
+        let mut render_graph = render_app.world.resource_mut::<render_graph::RenderGraph>();
+        render_graph.add_node("sand_capture", CaptureNode);
+        render_graph.add_node_edge("sand_node", "sand_capture");
+    }
+}
+
+#[derive(Resource)]
+struct CpuCapture(Sender<CapturedFrame>);
+
+// the CPU backend draws into the image in the main world, so there is nothing
+// to wait for
+fn capture_cpu_frame(
+    capture: Res<CpuCapture>,
+    cpu: Res<CpuSim>,
+    image: Option<Res<SandImage>>,
+    images: Res<Assets<Image>>,
+) {
+    let (Some(sim), Some(image)) = (&cpu.0, image) else {
+        return;
+    };
+    if let Some(image) = images.get(&image.0) {
+        let _ = capture.0.send(CapturedFrame {
+            tick: sim.tick,
+            width: sim.width,
+            height: sim.height,
+            pixels: image.data.clone(),
+        });
+    }
+}
+
+#[derive(Resource)]
+struct FrameCapture {
+    buffer: Buffer,
+    size: (u32, u32),
+    padded_bytes_per_row: u32,
+    sender: Sender<CapturedFrame>,
+}
+
+// whether the CaptureNode copied anything this frame
+fn has_frame(world: &World) -> bool {
+    world.resource::<SandSteps>().initialized
+        && world.get_resource::<SandImage>().is_some_and(|image| {
+            world
+                .resource::<RenderAssets<Image>>()
+                .get(&image.0)
+                .is_some()
+        })
+}
+
+fn read_frame_capture(world: &mut World) {
+    if !has_frame(world) {
+        return;
+    }
+    let capture = world.resource::<FrameCapture>();
+    let slice = capture.buffer.slice(..);
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result.is_ok());
+    });
+    world.resource::<RenderDevice>().poll(wgpu::Maintain::Wait);
+    if receiver.recv() != Ok(true) {
+        error!("could not map the capture buffer");
+        return;
+    }
+
+    let row_bytes = capture.size.0 as usize * 4;
+    let pixels = slice
+        .get_mapped_range()
+        .chunks_exact(capture.padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect();
+    capture.buffer.unmap();
+    let _ = capture.sender.send(CapturedFrame {
+        tick: world.resource::<SandSteps>().tick,
+        width: capture.size.0,
+        height: capture.size.1,
+        pixels,
+    });
+}
+
+#[derive(Default)]
+struct CaptureNode;
+
+impl render_graph::Node for CaptureNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        if !has_frame(world) {
+            return Ok(());
+        }
+        let image = world.resource::<SandImage>();
+        let texture = &world
+            .resource::<RenderAssets<Image>>()
+            .get(&image.0)
+            .unwrap()
+            .texture;
+        let capture = world.resource::<FrameCapture>();
+        render_context.command_encoder().copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &capture.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(capture.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: capture.size.0,
+                height: capture.size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+}
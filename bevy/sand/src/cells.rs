@@ -0,0 +1,46 @@
+use bevy::{
+    prelude::*,
+    render::{render_resource::*, renderer::*},
+};
+
+use crate::config::SandConfig;
+
+// material id in the first channel, per-cell flags in the second, the bits of
+// the temperature as f32 in the third and the seed of the grain, which only
+// drawing reads, in the fourth
+pub const CELL_FORMAT: TextureFormat = TextureFormat::Rgba32Uint;
+pub const CELL_CHANNELS: usize = 4;
+
+// The simulation state, kept apart from the image on screen. The update passes
+// ping-pong between the two textures and always leave the latest state in [0].
+#[derive(Resource)]
+pub struct SandCells {
+    pub textures: [Texture; 2],
+    pub views: [TextureView; 2],
+}
+
+pub fn create_cells(render_device: &RenderDevice, config: &SandConfig) -> SandCells {
+    let textures = [0, 1].map(|_| {
+        render_device.create_texture(&TextureDescriptor {
+            label: Some("sand_cells"),
+            size: Extent3d {
+                width: config.size.0,
+                height: config.size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: CELL_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    });
+    let views = textures
+        .each_ref()
+        .map(|texture| texture.create_view(&TextureViewDescriptor::default()));
+    SandCells { textures, views }
+}
@@ -0,0 +1,81 @@
+use bevy::{
+    prelude::*,
+    render::{render_resource::*, renderer::*},
+};
+
+use crate::config::SandConfig;
+
+// The update passes only run on chunks where something happened in the last
+// tick, in the chunk itself or next to it. A chunk is one workgroup of update
+// invocations, i.e. a square of workgroup_size² Margolus blocks.
+//
+// `changed` holds two flags per chunk, the passes of a tick set the ones in
+// half tick % 2. At the start of every tick the schedule kernel reads the other
+// half, written by the tick before, and decides the mode of every chunk. The
+// chunks next to a changed one are updated, the ring around those only copies
+// its cells from one texture to the other so that the updated chunks never
+// read or leave behind cells a pass skipped. The updated and copied chunks go
+// into `active` and are counted in `dispatch`, the arguments of the indirect
+// update dispatch. `dispatch` is only bound to the schedule kernels.
+#[derive(Resource)]
+pub struct SandChunks {
+    // chunks per row and column
+    pub size: UVec2,
+    pub changed: Buffer,
+    pub active: Buffer,
+    // skipped, copied or updated, see `schedule` in sand.wgsl
+    pub modes: Buffer,
+    pub dispatch: Buffer,
+}
+
+impl SandChunks {
+    pub fn count(&self) -> u32 {
+        self.size.x * self.size.y
+    }
+
+    // for when every cell may have changed, like after loading a snapshot
+    pub fn wake_all(&self, render_queue: &RenderQueue) {
+        let ones = vec![1u32; self.count() as usize * 2];
+        render_queue.write_buffer(&self.changed, 0, bevy::core::cast_slice(&ones));
+    }
+}
+
+pub fn create_chunks(render_device: &RenderDevice, config: &SandConfig) -> SandChunks {
+    // the blocks of the odd phase reach one cell past the edge
+    let size = UVec2::new(
+        config.workgroups(config.size.0 / 2 + 1),
+        config.workgroups(config.size.1 / 2 + 1),
+    );
+    let count = (size.x * size.y) as usize;
+
+    // everything starts awake
+    let changed = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("sand_chunks_changed"),
+        contents: bevy::core::cast_slice(&vec![1u32; count * 2]),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    });
+    let active = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_chunks_active"),
+        size: (count * std::mem::size_of::<u32>()) as u64,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    let modes = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_chunks_modes"),
+        size: (count * std::mem::size_of::<u32>()) as u64,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    let dispatch = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("sand_chunks_dispatch"),
+        contents: bevy::core::cast_slice(&[0u32, 1, 1]),
+        usage: BufferUsages::STORAGE | BufferUsages::INDIRECT,
+    });
+    SandChunks {
+        size,
+        changed,
+        active,
+        modes,
+        dispatch,
+    }
+}
@@ -1,9 +1,120 @@
-pub struct Config {
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::scene::SceneGenerator;
+
+// The one source of truth for the size of the world. Everything that depends
+// on it, from the window to the shaders, reads it from this resource.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SandConfig {
     pub size: (u32, u32),
+    // the compute kernels run in square workgroups of this many cells a side
     pub workgroup_size: u32,
+    pub seed: u32,
+    pub scene: SceneGenerator,
+    // GPU memory the undo history may take, in MiB
+    pub undo_budget_mb: u32,
+    // file F5 saves the world to and F9 loads it from, see snapshot.rs
+    pub snapshot_path: PathBuf,
+}
+
+impl Default for SandConfig {
+    fn default() -> Self {
+        SandConfig {
+            size: (1280, 1280),
+            workgroup_size: 8,
+            seed: 1,
+            scene: SceneGenerator::default(),
+            undo_budget_mb: 256,
+            snapshot_path: PathBuf::from("snapshot.png"),
+        }
+    }
 }
 
-pub const CONFIG: Config = Config {
-    size: (1280, 1280),
-    workgroup_size: 8,
-};
+// the arguments every binary takes to set up the world
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// RON file with any of the settings, e.g. `(size: (800, 600), workgroup_size: 16)`
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// world width in cells
+    #[arg(long)]
+    width: Option<u32>,
+    /// world height in cells
+    #[arg(long)]
+    height: Option<u32>,
+    /// side of the square compute workgroups, at most 16
+    #[arg(long)]
+    workgroup_size: Option<u32>,
+    /// seed of the random numbers of the simulation
+    #[arg(long)]
+    seed: Option<u32>,
+    /// layout the world starts from
+    #[arg(long, value_enum)]
+    scene: Option<SceneGenerator>,
+    /// GPU memory for the undo history in MiB, 0 turns it off
+    #[arg(long)]
+    undo_budget_mb: Option<u32>,
+    /// file to save snapshots to and load them from, a .png or binary
+    #[arg(long)]
+    snapshot_path: Option<PathBuf>,
+}
+
+impl SandConfig {
+    // the config file comes first, arguments on the command line override it
+    pub fn from_config_args(args: &ConfigArgs) -> anyhow::Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("could not read {}", path.display()))?;
+                ron::de::from_str(&text)
+                    .with_context(|| format!("could not parse {}", path.display()))?
+            }
+            None => SandConfig::default(),
+        };
+        if let Some(width) = args.width {
+            config.size.0 = width;
+        }
+        if let Some(height) = args.height {
+            config.size.1 = height;
+        }
+        if let Some(workgroup_size) = args.workgroup_size {
+            config.workgroup_size = workgroup_size;
+        }
+        if let Some(seed) = args.seed {
+            config.seed = seed;
+        }
+        if let Some(scene) = args.scene {
+            config.scene = scene;
+        }
+        if let Some(undo_budget_mb) = args.undo_budget_mb {
+            config.undo_budget_mb = undo_budget_mb;
+        }
+        if let Some(snapshot_path) = &args.snapshot_path {
+            config.snapshot_path = snapshot_path.clone();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.size.0 == 0 || self.size.1 == 0 {
+            bail!("the world must be at least one cell wide and high");
+        }
+        // wgpu guarantees 256 invocations per workgroup
+        if self.workgroup_size == 0 || self.workgroup_size * self.workgroup_size > 256 {
+            bail!("workgroup size must be between 1 and 16");
+        }
+        Ok(())
+    }
+
+    // workgroups needed to cover `cells` cells
+    pub fn workgroups(&self, cells: u32) -> u32 {
+        cells.div_ceil(self.workgroup_size)
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::{prelude::*, render::extract_resource::*};
+
+// the most ticks a frame runs when they are asked for with the keyboard
+pub const MAX_TICKS_PER_FRAME: u32 = 32;
+
+#[derive(Resource, Clone, ExtractResource)]
+pub struct SimulationControl {
+    pub paused: bool,
+    // ticks per frame while running
+    pub substeps: u32,
+    // ticks requested while paused, run on the next frame
+    pub pending_ticks: u32,
+    // what the render world dispatches this frame
+    pub ticks: u32,
+}
+
+impl Default for SimulationControl {
+    fn default() -> Self {
+        SimulationControl {
+            paused: false,
+            substeps: 1,
+            pending_ticks: 0,
+            ticks: 0,
+        }
+    }
+}
+
+impl SimulationControl {
+    pub fn step(&mut self, ticks: u32) {
+        self.pending_ticks += ticks;
+    }
+}
+
+// space pauses, period steps one tick (ten with shift) while paused, and the
+// up and down arrows change the number of ticks per frame. Steps asked for
+// faster than they run are spread over the next frames.
+pub fn update_simulation_control(
+    mut control: ResMut<SimulationControl>,
+    keys: Res<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        control.paused = !control.paused;
+        info!(
+            "simulation {}",
+            if control.paused { "paused" } else { "running" }
+        );
+    }
+    if keys.just_pressed(KeyCode::Period) && control.paused {
+        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        control.step(if shift { 10 } else { 1 });
+    }
+    if keys.just_pressed(KeyCode::Up) {
+        control.substeps = (control.substeps + 1).min(MAX_TICKS_PER_FRAME);
+        info!("ticks per frame: {}", control.substeps);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        control.substeps = control.substeps.saturating_sub(1).max(1);
+        info!("ticks per frame: {}", control.substeps);
+    }
+
+    control.ticks = if control.paused {
+        let ticks = control.pending_ticks.min(MAX_TICKS_PER_FRAME);
+        control.pending_ticks -= ticks;
+        ticks
+    } else {
+        control.substeps
+    };
+}
@@ -0,0 +1,125 @@
+use bevy::{prelude::*, render::renderer::RenderAdapter};
+
+use crate::{
+    brush::{BrushShape, BrushState},
+    config::SandConfig,
+    control::SimulationControl,
+    gravity::GravitySettings,
+    image::SandImage,
+    material::MaterialRegistry,
+    palette::SandPalette,
+    sim::Sim,
+    stats::SandStats,
+};
+
+// Where the world is simulated. The kernels in sand.wgsl need compute shaders,
+// which WebGL2 and some older drivers do not have. The world then runs on the
+// CPU model of sim.rs instead, which is far slower but gives the same result.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SandBackend {
+    Gpu,
+    Cpu,
+}
+
+impl SandBackend {
+    pub fn for_adapter(adapter: &RenderAdapter) -> Self {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+            SandBackend::Gpu
+        } else {
+            SandBackend::Cpu
+        }
+    }
+}
+
+// the world of the CPU backend, built once the material registry has loaded
+#[derive(Resource, Default)]
+pub struct CpuSim(pub Option<Sim>);
+
+// What SandNode and the StatsNode do on the GPU: runs the ticks of the frame,
+// counts the cells, stamps the brush and draws the cells into SandImage.
+#[allow(clippy::too_many_arguments)]
+pub fn run_cpu_sim(
+    mut cpu: ResMut<CpuSim>,
+    registry: Option<Res<MaterialRegistry>>,
+    config: Res<SandConfig>,
+    control: Res<SimulationControl>,
+    gravity: Res<GravitySettings>,
+    brush: Res<BrushState>,
+    palette: Res<SandPalette>,
+    image: Option<Res<SandImage>>,
+    mut images: ResMut<Assets<Image>>,
+    mut stats: ResMut<SandStats>,
+) {
+    let (Some(registry), Some(image)) = (registry, image) else {
+        return;
+    };
+    // the first frame only shows the scene, like the init kernel
+    let initialized = cpu.0.is_some();
+    let sim = cpu.0.get_or_insert_with(|| {
+        let mut sim = Sim::new(&config, &registry);
+        sim.generate(config.scene);
+        sim
+    });
+    // settled chunks may start moving again
+    if !initialized || gravity.is_changed() {
+        sim.gravity = gravity.clone();
+        sim.wake_all();
+    }
+    // the brush paints with the step of the first tick, like on the GPU
+    let tick = sim.tick;
+    if initialized {
+        if registry.is_changed() {
+            sim.set_materials(&registry);
+        }
+        let moved = (0..control.ticks).map(|_| sim.step()).sum();
+        *stats = SandStats::from_sim(sim, moved);
+    }
+    paint(sim, &brush, tick);
+
+    if let Some(image) = images.get_mut(&image.0) {
+        draw(sim, &palette, &mut image.data);
+    }
+}
+
+// see `paint` in sand.wgsl
+fn paint(sim: &mut Sim, brush: &BrushState, tick: u32) {
+    let (Some(material), Some(end), Some((origin, size))) =
+        (brush.stroke, brush.position, brush.bounds())
+    else {
+        return;
+    };
+    let start = brush.last_position.unwrap_or(end);
+    let stroke = end - start;
+    let radius = brush.radius as f32;
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            let location = origin + IVec2::new(x, y);
+            let point = location.as_vec2() + 0.5;
+            let along =
+                ((point - start).dot(stroke) / stroke.dot(stroke).max(0.0001)).clamp(0.0, 1.0);
+            let offset = (point - (start + stroke * along)).abs();
+            let inside = match brush.shape {
+                BrushShape::Circle => offset.length() <= radius,
+                BrushShape::Square => offset.max_element() <= radius,
+            };
+            if inside {
+                sim.paint(location.x, location.y, material, tick);
+            }
+        }
+    }
+}
+
+// see `draw` in draw.wgsl, into rgba8 pixels
+fn draw(sim: &Sim, palette: &SandPalette, pixels: &mut [u8]) {
+    for (cell, pixel) in sim.cells.iter().zip(pixels.chunks_exact_mut(4)) {
+        let Some(entry) = palette.0.get(cell.material as usize) else {
+            pixel.fill(0);
+            continue;
+        };
+        let noise = cell.seed as f32 / 4294967295.0 - 0.5;
+        let rgb = (entry.color.truncate() + noise * entry.variation).clamp(Vec3::ZERO, Vec3::ONE);
+        let color = rgb.extend(entry.color.w) * 255.0;
+        pixel.copy_from_slice(&color.round().to_array().map(|channel| channel as u8));
+    }
+}
@@ -2,16 +2,21 @@ use bevy::prelude::*;
 
 use crate::camera::MainCamera;
 
-pub fn draw_viewport_rect(mut gizmos: Gizmos, q: Query<&Camera, With<MainCamera>>) {
-    let camera = q.single();
+// outlines the part of the world the main camera shows, on the minimap
+pub fn draw_viewport_rect(
+    mut gizmos: Gizmos,
+    q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let (camera, transform) = q.single();
 
-    // the top-left and bottom-right coordinates
+    // the top-left and bottom-right corners of the window, in the world
     let view_port_rect = camera.logical_viewport_rect().unwrap();
+    let (Some(min), Some(max)) = (
+        camera.viewport_to_world_2d(transform, view_port_rect.min),
+        camera.viewport_to_world_2d(transform, view_port_rect.max),
+    ) else {
+        return;
+    };
 
-    gizmos.rect_2d(
-        view_port_rect.min,
-        0.0,
-        view_port_rect.size(),
-        Color::RED,
-    );
+    gizmos.rect_2d((min + max) / 2.0, 0.0, (max - min).abs(), Color::RED);
 }
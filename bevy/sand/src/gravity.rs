@@ -0,0 +1,190 @@
+use bevy::{
+    prelude::*,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+};
+
+use crate::chunks::SandChunks;
+
+// in the order gravity turns through with e
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum GravityDirection {
+    #[default]
+    Down,
+    Left,
+    Up,
+    Right,
+}
+
+impl GravityDirection {
+    const ALL: [GravityDirection; 4] = [
+        GravityDirection::Down,
+        GravityDirection::Left,
+        GravityDirection::Up,
+        GravityDirection::Right,
+    ];
+
+    pub fn rotated(self, quarter_turns: i32) -> Self {
+        Self::ALL[(self as i32 + quarter_turns).rem_euclid(4) as usize]
+    }
+
+    // in cell coordinates, y grows downwards
+    pub fn down(self) -> IVec2 {
+        match self {
+            GravityDirection::Down => IVec2::new(0, 1),
+            GravityDirection::Left => IVec2::new(-1, 0),
+            GravityDirection::Up => IVec2::new(0, -1),
+            GravityDirection::Right => IVec2::new(1, 0),
+        }
+    }
+
+    // what is to the right when looking at the world with gravity pointing down
+    pub fn lateral(self) -> IVec2 {
+        let down = self.down();
+        IVec2::new(down.y, -down.x)
+    }
+
+    // The update rules are written for gravity pointing down. The cell at
+    // `index` of a block as they see it (0 1 over 2 3) is at this offset from
+    // the top left corner of the block in the world.
+    pub fn block_offset(self, index: usize) -> IVec2 {
+        let corner = IVec2::new(index as i32 % 2, index as i32 / 2) * 2 - 1;
+        (self.lateral() * corner.x + self.down() * corner.y + 1) / 2
+    }
+}
+
+// Wind that changes over the world, one velocity for every square of
+// `cell_size` cells. Anything outside the field only gets the global wind.
+#[derive(Clone, Debug)]
+pub struct WindField {
+    pub cell_size: u32,
+    // squares per row and column
+    pub size: UVec2,
+    pub velocities: Vec<Vec2>,
+}
+
+impl WindField {
+    // still air over a world of `world_size` cells
+    pub fn new(world_size: (u32, u32), cell_size: u32) -> Self {
+        let size = UVec2::new(
+            world_size.0.div_ceil(cell_size),
+            world_size.1.div_ceil(cell_size),
+        );
+        WindField {
+            cell_size,
+            size,
+            velocities: vec![Vec2::ZERO; (size.x * size.y) as usize],
+        }
+    }
+
+    pub fn set(&mut self, square: UVec2, velocity: Vec2) {
+        if square.x < self.size.x && square.y < self.size.y {
+            self.velocities[(square.y * self.size.x + square.x) as usize] = velocity;
+        }
+    }
+
+    // see `windAt` in sand.wgsl
+    pub fn at(&self, location: IVec2) -> Vec2 {
+        let square = location.max(IVec2::ZERO).as_uvec2() / self.cell_size;
+        if square.x < self.size.x && square.y < self.size.y {
+            self.velocities[(square.y * self.size.x + square.x) as usize]
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
+
+// Which way things fall, and the wind that blows them sideways. The wind is
+// the chance per pass that a cell as dense as empty moves one cell downwind,
+// denser cells are blown less often.
+#[derive(Resource, Clone, Default, Debug, ExtractResource)]
+pub struct GravitySettings {
+    pub direction: GravityDirection,
+    pub wind: Vec2,
+    // added to `wind`
+    pub wind_field: Option<WindField>,
+}
+
+impl GravitySettings {
+    pub fn wind_at(&self, location: IVec2) -> Vec2 {
+        let field = self
+            .wind_field
+            .as_ref()
+            .map_or(Vec2::ZERO, |field| field.at(location));
+        self.wind + field
+    }
+}
+
+// q and e turn gravity a quarter counterclockwise and clockwise, the left and
+// right arrows change the wind
+pub fn update_gravity(mut gravity: ResMut<GravitySettings>, keys: Res<Input<KeyCode>>) {
+    let mut turn = 0;
+    if keys.just_pressed(KeyCode::Q) {
+        turn -= 1;
+    }
+    if keys.just_pressed(KeyCode::E) {
+        turn += 1;
+    }
+    if turn != 0 {
+        gravity.direction = gravity.direction.rotated(turn);
+        info!("gravity {:?}", gravity.direction);
+    }
+
+    let mut wind = 0.0;
+    if keys.just_pressed(KeyCode::Left) {
+        wind -= 0.1;
+    }
+    if keys.just_pressed(KeyCode::Right) {
+        wind += 0.1;
+    }
+    if wind != 0.0 {
+        gravity.wind.x = (gravity.wind.x + wind).clamp(-1.0, 1.0);
+        info!("wind {:.1}", gravity.wind.x);
+    }
+}
+
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct GpuGravity {
+    pub down: IVec2,
+    pub wind: Vec2,
+    pub field_size: UVec2,
+    pub field_cell_size: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct GravityBuffer {
+    pub uniform: UniformBuffer<GpuGravity>,
+    pub field: StorageBuffer<Vec<Vec2>>,
+}
+
+pub fn prepare_gravity(
+    gravity: Res<GravitySettings>,
+    mut gravity_buffer: ResMut<GravityBuffer>,
+    chunks: Res<SandChunks>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !gravity.is_changed() {
+        return;
+    }
+    // settled chunks may start moving again
+    chunks.wake_all(&render_queue);
+
+    // an empty field still needs a buffer to bind
+    let (field_size, field_cell_size, velocities) = match &gravity.wind_field {
+        Some(field) => (field.size, field.cell_size, field.velocities.clone()),
+        None => (UVec2::ZERO, 1, vec![Vec2::ZERO]),
+    };
+    gravity_buffer.uniform.set(GpuGravity {
+        down: gravity.direction.down(),
+        wind: gravity.wind,
+        field_size,
+        field_cell_size,
+    });
+    gravity_buffer.field.set(velocities);
+    gravity_buffer
+        .uniform
+        .write_buffer(&render_device, &render_queue);
+    gravity_buffer
+        .field
+        .write_buffer(&render_device, &render_queue);
+}
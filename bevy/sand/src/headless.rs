@@ -0,0 +1,101 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    prelude::*,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+use crate::{
+    capture::{CapturePlugin, CaptureReceiver},
+    config::SandConfig,
+    control::SimulationControl,
+    cpu::SandBackend,
+    image::{create_texture, SandImage},
+    plugin::SandPlugin,
+};
+
+#[derive(Resource, Clone, Debug)]
+pub struct HeadlessSettings {
+    // the run stops once this many ticks have been simulated, rounded up to a
+    // multiple of `every`
+    pub ticks: u32,
+    // a frame is written every this many ticks, starting with the initial scene
+    pub every: u32,
+    pub output: PathBuf,
+}
+
+// Simulates the world without a window and writes SandImage to
+// `output/tick_NNNNNN.png` every `every` ticks. Every frame runs exactly
+// `every` ticks and is read back before the next one starts, so the same
+// config always writes the same images.
+pub fn run_headless(config: SandConfig, settings: HeadlessSettings) -> anyhow::Result<()> {
+    fs::create_dir_all(&settings.output)?;
+
+    App::new()
+        .insert_resource(config)
+        .insert_resource(SimulationControl {
+            substeps: settings.every,
+            ..default()
+        })
+        .insert_resource(settings)
+        .add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+            SandPlugin,
+            CapturePlugin,
+        ))
+        .add_systems(Startup, setup_headless)
+        .add_systems(
+            Update,
+            write_frames.run_if(resource_exists::<CaptureReceiver>()),
+        )
+        .run();
+    Ok(())
+}
+
+fn setup_headless(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    config: Res<SandConfig>,
+    backend: Res<SandBackend>,
+) {
+    let image = create_texture(&mut images, config.size, *backend);
+    commands.insert_resource(SandImage(image));
+}
+
+fn write_frames(
+    receiver: Res<CaptureReceiver>,
+    settings: Res<HeadlessSettings>,
+    mut written: Local<Option<u32>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for frame in receiver.0.try_iter() {
+        // the init kernel may run for a few frames before the first tick
+        if frame.tick % settings.every != 0 || *written == Some(frame.tick) {
+            continue;
+        }
+        let path = settings.output.join(format!("tick_{:06}.png", frame.tick));
+        match image::save_buffer(
+            &path,
+            &frame.pixels,
+            frame.width,
+            frame.height,
+            image::ColorType::Rgba8,
+        ) {
+            Ok(()) => info!("wrote {}", path.display()),
+            Err(err) => error!("could not write {}: {err}", path.display()),
+        }
+        *written = Some(frame.tick);
+        if frame.tick >= settings.ticks {
+            exit.send(AppExit);
+        }
+    }
+}
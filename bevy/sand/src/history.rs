@@ -0,0 +1,212 @@
+use bevy::{
+    prelude::*,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+};
+
+use crate::{
+    brush::BrushState,
+    cells::{SandCells, CELL_CHANNELS, CELL_FORMAT},
+    chunks::SandChunks,
+    config::SandConfig,
+    control::SimulationControl,
+    snapshot::SnapshotRequests,
+};
+
+// a copy between cells[0] and a slot of the history, done before anything
+// else touches the cells this frame
+#[derive(Clone, Copy, Debug)]
+pub enum HistoryCopy {
+    Save(usize),
+    Restore(usize),
+}
+
+// A ring of copies of the world taken on the GPU right before every edit. The
+// oldest ones are dropped once the copies fill `undo_budget_mb`. While the
+// simulation is paused ctrl+z goes back through them and ctrl+y forward again.
+//
+// The undo states are the `undo` slots before `head`, the redo states the
+// `redo` slots after it. After an undo the world is the one in `head`.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct UndoHistory {
+    capacity: usize,
+    head: usize,
+    undo: usize,
+    redo: usize,
+    // set for exactly one frame
+    pub copies: Vec<HistoryCopy>,
+}
+
+impl FromWorld for UndoHistory {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<SandConfig>();
+        let cells = config.size.0 as u64 * config.size.1 as u64;
+        let budget = config.undo_budget_mb as u64 * 1024 * 1024;
+        let capacity = (budget / (cells * CELL_CHANNELS as u64 * 4)) as usize;
+        // one slot is not enough to undo and redo again
+        if capacity < 2 && config.undo_budget_mb > 0 {
+            warn!(
+                "an undo budget of {} MiB does not fit two copies of the world",
+                config.undo_budget_mb
+            );
+        }
+        UndoHistory {
+            capacity: if capacity < 2 { 0 } else { capacity },
+            head: 0,
+            undo: 0,
+            redo: 0,
+            copies: Vec::new(),
+        }
+    }
+}
+
+impl UndoHistory {
+    fn slot(&self, offset: isize) -> usize {
+        (self.head as isize + offset).rem_euclid(self.capacity as isize) as usize
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.redo > 0
+    }
+
+    // keeps the world as it is right now
+    pub fn record(&mut self) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.copies.push(HistoryCopy::Save(self.head));
+        self.head = self.slot(1);
+        self.undo = (self.undo + 1).min(self.capacity);
+        self.redo = 0;
+    }
+
+    pub fn undo(&mut self) {
+        if !self.can_undo() {
+            return;
+        }
+        // keep the world to redo to, in the slot of the oldest undo state if
+        // the ring is full
+        if self.redo == 0 {
+            self.copies.push(HistoryCopy::Save(self.head));
+            self.undo = self.undo.min(self.capacity - 1);
+        }
+        self.head = self.slot(-1);
+        self.copies.push(HistoryCopy::Restore(self.head));
+        self.undo -= 1;
+        self.redo += 1;
+    }
+
+    pub fn redo(&mut self) {
+        if !self.can_redo() {
+            return;
+        }
+        self.head = self.slot(1);
+        self.copies.push(HistoryCopy::Restore(self.head));
+        self.redo -= 1;
+        self.undo += 1;
+    }
+
+    // the world moved on from the state redo would go forward from
+    pub fn forget_redo(&mut self) {
+        self.redo = 0;
+    }
+}
+
+// Ctrl+z undoes and ctrl+y (or ctrl+shift+z) redoes while paused. Runs after
+// the brush and snapshot keys, so strokes starting and snapshots loading this
+// frame are recorded before they change anything.
+pub fn update_history(
+    mut history: ResMut<UndoHistory>,
+    keys: Res<Input<KeyCode>>,
+    control: Res<SimulationControl>,
+    brush: Res<BrushState>,
+    requests: Res<SnapshotRequests>,
+    mut painting: Local<bool>,
+) {
+    history.copies.clear();
+
+    let stroke_started = brush.stroke.is_some() && !*painting;
+    *painting = brush.stroke.is_some();
+    if stroke_started || requests.load.is_some() {
+        history.record();
+    } else if control.ticks > 0 {
+        history.forget_redo();
+    }
+
+    if !control.paused {
+        return;
+    }
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Z) && !shift && history.can_undo() {
+        history.undo();
+        info!("undo");
+    } else if (keys.just_pressed(KeyCode::Y) || keys.just_pressed(KeyCode::Z)) && history.can_redo()
+    {
+        history.redo();
+        info!("redo");
+    }
+}
+
+// the slots of the ring, created the first time they are saved to
+#[derive(Resource, Default)]
+pub struct HistoryTextures(Vec<Texture>);
+
+// runs before load_snapshot, whose write to the cells only lands after this
+// has been submitted
+pub fn apply_history(
+    history: Res<UndoHistory>,
+    mut textures: ResMut<HistoryTextures>,
+    cells: Res<SandCells>,
+    chunks: Res<SandChunks>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if history.copies.is_empty() {
+        return;
+    }
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("sand_history"),
+    });
+    let size = cells.textures[0].size();
+    for copy in &history.copies {
+        match *copy {
+            HistoryCopy::Save(slot) => {
+                while textures.0.len() <= slot {
+                    textures
+                        .0
+                        .push(render_device.create_texture(&TextureDescriptor {
+                            label: Some("sand_history"),
+                            size,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: TextureDimension::D2,
+                            format: CELL_FORMAT,
+                            usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+                            view_formats: &[],
+                        }));
+                }
+                encoder.copy_texture_to_texture(
+                    cells.textures[0].as_image_copy(),
+                    textures.0[slot].as_image_copy(),
+                    size,
+                );
+            }
+            HistoryCopy::Restore(slot) => {
+                encoder.copy_texture_to_texture(
+                    textures.0[slot].as_image_copy(),
+                    cells.textures[0].as_image_copy(),
+                    size,
+                );
+                chunks.wake_all(&render_queue);
+            }
+        }
+    }
+    render_queue.submit([encoder.finish()]);
+}
@@ -1,20 +1,23 @@
-
 use bevy::{
     prelude::*,
-    render::{render_resource::*, texture::*, extract_resource::*, },
+    render::{extract_resource::*, render_resource::*, texture::*},
 };
 
-use crate::config::CONFIG;
-
+use crate::cpu::SandBackend;
 
+// what ends up on screen, painted from the cell state by the draw pass
 #[derive(Resource, Clone, Deref, ExtractResource)]
 pub struct SandImage(pub Handle<Image>);
 
-pub fn create_texture(images: &mut Assets<Image>) -> Handle<Image> {
+pub fn create_texture(
+    images: &mut Assets<Image>,
+    size: (u32, u32),
+    backend: SandBackend,
+) -> Handle<Image> {
     let mut image = Image::new_fill(
         Extent3d {
-            width: CONFIG.size.0,
-            height: CONFIG.size.1,
+            width: size.0,
+            height: size.1,
             depth_or_array_layers: 1,
         },
         TextureDimension::D2,
@@ -22,8 +25,11 @@ pub fn create_texture(images: &mut Assets<Image>) -> Handle<Image> {
         TextureFormat::Rgba8Unorm,
     );
     image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
-    image.sampler_descriptor = ImageSampler::nearest();
+        TextureUsages::COPY_DST | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING;
+    // the CPU backend writes the pixels into the image instead of a draw pass
+    if backend == SandBackend::Gpu {
+        image.texture_descriptor.usage |= TextureUsages::STORAGE_BINDING;
+    }
+    image.sampler = ImageSampler::nearest();
     images.add(image)
 }
-
@@ -0,0 +1,114 @@
+use bevy::{prelude::*, render::pipelined_rendering::PipelinedRenderingPlugin, window::*};
+
+use camera::{setup_camera, update_camera, update_minimap};
+pub use config::SandConfig;
+use cpu::SandBackend;
+use debug::draw_viewport_rect;
+pub use headless::{run_headless, HeadlessSettings};
+use image::{create_texture, SandImage};
+use plugin::SandPlugin;
+use replay::ReplayPlugin;
+pub use replay::{Recording, Session};
+pub use stats::SandStats;
+
+mod bind_group;
+mod bodies;
+mod brush;
+mod camera;
+mod capture;
+mod cells;
+mod chunks;
+pub mod config;
+mod control;
+mod cpu;
+mod debug;
+pub mod gravity;
+mod headless;
+mod history;
+mod image;
+pub mod material;
+mod node;
+mod overlay;
+mod palette;
+mod pipeline;
+mod plugin;
+mod replay;
+pub mod scene;
+pub mod sim;
+mod snapshot;
+mod stats;
+mod step;
+mod time;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
+pub enum GameState {
+    #[default]
+    Setup,
+    Playing,
+}
+
+const MAX_WINDOW_SIZE: (u32, u32) = (1280, 960);
+
+pub fn run(config: SandConfig, session: Session) {
+    // the window starts out at the size of the world, if that fits
+    let res = WindowResolution::new(
+        config.size.0.min(MAX_WINDOW_SIZE.0) as f32,
+        config.size.1.min(MAX_WINDOW_SIZE.1) as f32,
+    );
+
+    let mut default_plugins = DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            resolution: res,
+            title: "Sand".to_string(),
+            resizable: true,
+            ..default()
+        }),
+        ..default()
+    });
+    // the render world runs alongside the next frame otherwise, and what the
+    // GPU reads back arrives a frame early or late depending on the timing
+    if session.is_deterministic() {
+        default_plugins = default_plugins.disable::<PipelinedRenderingPlugin>();
+    }
+
+    App::new()
+        .insert_resource(config)
+        .add_plugins((default_plugins, SandPlugin, ReplayPlugin(session)))
+        .add_state::<GameState>()
+        .add_systems(Startup, setup_camera)
+        .add_systems(OnEnter(GameState::Setup), setup)
+        .add_systems(OnExit(GameState::Playing), teardown)
+        .add_systems(
+            Update,
+            (update_camera, update_minimap, draw_viewport_rect)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    config: Res<SandConfig>,
+    backend: Res<SandBackend>,
+) {
+    let image = create_texture(&mut images, config.size, *backend);
+    commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(config.size.0 as f32, config.size.1 as f32)),
+            ..default()
+        },
+        texture: image.clone(),
+        ..default()
+    });
+
+    commands.insert_resource(SandImage(image));
+    next_state.set(GameState::Playing);
+}
+
+fn teardown(mut commands: Commands, entities: Query<Entity, (Without<Camera>, Without<Window>)>) {
+    for entity in &entities {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,239 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    reflect::TypePath,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::scene::SceneMaterials;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MaterialKind {
+    Static,
+    Powder,
+    Liquid,
+    Gas,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MaterialDefinition {
+    pub name: String,
+    pub kind: MaterialKind,
+    // heavier materials sink through lighter ones
+    pub density: f32,
+    // chance per pass that a resting liquid or gas moves sideways
+    pub dispersion: f32,
+    pub color: [f32; 4],
+    pub color_variation: f32,
+    // degrees a new cell of the material starts at
+    #[serde(default = "ambient_temperature")]
+    pub temperature: f32,
+    // share of the temperature difference to a neighbour exchanged per pass, in [0, 1]
+    #[serde(default)]
+    pub conductivity: f32,
+    // stays at its own temperature, like fire
+    #[serde(default)]
+    pub heat_source: bool,
+    #[serde(default)]
+    pub above: Option<Transition>,
+    #[serde(default)]
+    pub below: Option<Transition>,
+    #[serde(default)]
+    pub decay: Option<Decay>,
+}
+
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+fn ambient_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+// turns into another material when hotter than (above) or colder than (below)
+// the temperature
+#[derive(Deserialize, Clone, Debug)]
+pub struct Transition {
+    pub temperature: f32,
+    pub into: String,
+}
+
+// turns into another material with the chance per pass
+#[derive(Deserialize, Clone, Debug)]
+pub struct Decay {
+    pub chance: f32,
+    pub into: String,
+}
+
+// Every material the simulation knows about. A material's id is its index in
+// the list, id 0 is the empty cell.
+#[derive(Asset, Resource, TypePath, Deserialize, Clone, Debug, ExtractResource)]
+pub struct MaterialRegistry {
+    pub materials: Vec<MaterialDefinition>,
+}
+
+impl MaterialRegistry {
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(|id| id as u32)
+    }
+
+    // names used in transitions that no material has
+    pub fn unknown_names(&self) -> Vec<&str> {
+        self.materials
+            .iter()
+            .flat_map(|material| {
+                let above = material.above.as_ref().map(|above| above.into.as_str());
+                let below = material.below.as_ref().map(|below| below.into.as_str());
+                let decay = material.decay.as_ref().map(|decay| decay.into.as_str());
+                [above, below, decay]
+            })
+            .flatten()
+            .filter(|name| self.id(name).is_none())
+            .collect()
+    }
+
+    pub fn gpu_materials(&self) -> Vec<GpuMaterial> {
+        self.materials
+            .iter()
+            .map(|material| GpuMaterial::new(material, self))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct MaterialRegistryLoader;
+
+impl AssetLoader for MaterialRegistryLoader {
+    type Asset = MaterialRegistry;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<MaterialRegistry, anyhow::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["materials.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct MaterialRegistryHandle(pub Handle<MaterialRegistry>);
+
+pub fn load_material_registry(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MaterialRegistryHandle(
+        asset_server.load("sand.materials.ron"),
+    ));
+}
+
+// (re)insert the registry resource whenever the asset finishes loading or changes on disk
+pub fn apply_material_registry(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<MaterialRegistry>>,
+    registries: Res<Assets<MaterialRegistry>>,
+    handle: Res<MaterialRegistryHandle>,
+) {
+    for event in events.read() {
+        if event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0) {
+            if let Some(registry) = registries.get(&handle.0) {
+                let names: Vec<&str> = registry.materials.iter().map(|m| m.name.as_str()).collect();
+                info!("loaded materials: {}", names.join(", "));
+                for name in registry.unknown_names() {
+                    warn!("no material called {name}, transitions into it are ignored");
+                }
+                for name in SceneMaterials::NAMES {
+                    if registry.id(name).is_none() {
+                        warn!("no material called {name}, the scenes are built without it");
+                    }
+                }
+                commands.insert_resource(registry.clone());
+            }
+        }
+    }
+}
+
+// id of a transition that does not happen
+pub const NO_MATERIAL: u32 = u32::MAX;
+
+// A material as the shaders see it, with the names in transitions resolved to ids.
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct GpuMaterial {
+    pub color: Vec4,
+    pub kind: u32,
+    pub density: f32,
+    pub dispersion: f32,
+    pub color_variation: f32,
+    pub temperature: f32,
+    pub conductivity: f32,
+    pub heat_source: u32,
+    pub above_temperature: f32,
+    pub above_into: u32,
+    pub below_temperature: f32,
+    pub below_into: u32,
+    pub decay_chance: f32,
+    pub decay_into: u32,
+}
+
+impl GpuMaterial {
+    pub fn new(material: &MaterialDefinition, registry: &MaterialRegistry) -> Self {
+        let resolve = |name: &str| registry.id(name).unwrap_or(NO_MATERIAL);
+        let (above_temperature, above_into) =
+            material.above.as_ref().map_or((0.0, NO_MATERIAL), |above| {
+                (above.temperature, resolve(&above.into))
+            });
+        let (below_temperature, below_into) =
+            material.below.as_ref().map_or((0.0, NO_MATERIAL), |below| {
+                (below.temperature, resolve(&below.into))
+            });
+        let (decay_chance, decay_into) =
+            material.decay.as_ref().map_or((0.0, NO_MATERIAL), |decay| {
+                (decay.chance, resolve(&decay.into))
+            });
+        GpuMaterial {
+            color: Vec4::from(material.color),
+            kind: material.kind as u32,
+            density: material.density,
+            dispersion: material.dispersion,
+            color_variation: material.color_variation,
+            temperature: material.temperature,
+            conductivity: material.conductivity,
+            heat_source: material.heat_source as u32,
+            above_temperature,
+            above_into,
+            below_temperature,
+            below_into,
+            decay_chance,
+            decay_into,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MaterialBuffer(pub StorageBuffer<Vec<GpuMaterial>>);
+
+pub fn prepare_materials(
+    registry: Res<MaterialRegistry>,
+    mut material_buffer: ResMut<MaterialBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !registry.is_changed() {
+        return;
+    }
+    material_buffer.0.set(registry.gpu_materials());
+    material_buffer
+        .0
+        .write_buffer(&render_device, &render_queue);
+}
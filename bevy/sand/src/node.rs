@@ -1,8 +1,22 @@
+use std::sync::atomic::Ordering;
+
+use crate::{
+    bind_group::SandBindGroups,
+    bodies::BodyBuffers,
+    brush::BrushState,
+    cells::SandCells,
+    chunks::SandChunks,
+    config::SandConfig,
+    control::SimulationControl,
+    overlay::Overlay,
+    pipeline::SandPipeline,
+    replay::{TickHashBindGroup, TickHashPipeline},
+    step::SandSteps,
+};
 use bevy::{
     prelude::*,
-    render::{*, renderer::*, render_resource::*},
+    render::{render_resource::*, renderer::*, *},
 };
-use crate::{config::CONFIG, pipeline::SandPipeline, bind_group::SandBindGroup};
 
 enum SandState {
     Loading,
@@ -30,21 +44,61 @@ impl render_graph::Node for SandNode {
         // if the corresponding pipeline has loaded, transition to the next stage
         match self.state {
             SandState::Loading => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline)
-                {
+                let pipelines_loaded = [
+                    pipeline.init_pipeline,
+                    pipeline.draw_pipeline,
+                    pipeline.heatmap_pipeline,
+                    pipeline.shaded_pipeline,
+                    pipeline.chunks_pipeline,
+                ]
+                .into_iter()
+                .all(|id| {
+                    matches!(
+                        pipeline_cache.get_compute_pipeline_state(id),
+                        CachedPipelineState::Ok(_)
+                    )
+                });
+                // bind groups only show up once the material registry is loaded
+                if pipelines_loaded && world.contains_resource::<SandBindGroups>() {
                     self.state = SandState::Init;
+                    world.resource_mut::<SandSteps>().initialized = true;
                 }
             }
             SandState::Init => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
-                {
+                let pipelines_loaded = [
+                    pipeline.update_pipeline,
+                    pipeline.paint_pipeline,
+                    pipeline.reset_schedule_pipeline,
+                    pipeline.schedule_pipeline,
+                    pipeline.stamp_bodies_pipeline,
+                    pipeline.place_displaced_pipeline,
+                ]
+                .into_iter()
+                .all(|id| {
+                    matches!(
+                        pipeline_cache.get_compute_pipeline_state(id),
+                        CachedPipelineState::Ok(_)
+                    )
+                });
+                // the first tick is hashed too when recording or replaying
+                let hash_loaded = world.get_resource::<TickHashPipeline>().is_none_or(|hash| {
+                    matches!(
+                        pipeline_cache.get_compute_pipeline_state(hash.pipeline),
+                        CachedPipelineState::Ok(_)
+                    )
+                });
+                if pipelines_loaded && hash_loaded {
                     self.state = SandState::Update;
                 }
             }
             SandState::Update => {}
         }
+
+        // the passes dispatched this frame were prepared with the current tick
+        if let SandState::Update = self.state {
+            let ticks = world.resource::<SimulationControl>().ticks;
+            world.resource_mut::<SandSteps>().tick += ticks;
+        }
     }
 
     fn run(
@@ -53,36 +107,162 @@ impl render_graph::Node for SandNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), render_graph::NodeRunError> {
-        let texture_bind_group = &world.resource::<SandBindGroup>().0;
+        if let SandState::Loading = self.state {
+            return Ok(());
+        }
+
+        let bind_groups = world.resource::<SandBindGroups>();
+        let steps = world.resource::<SandSteps>();
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<SandPipeline>();
+        let config = world.resource::<SandConfig>();
+        let chunks = world.resource::<SandChunks>();
+        let (width, height) = config.size;
+
+        // draw the bodies into cells[0] before the tick moves anything around them,
+        // only on frames that tick so that a paused world is not changed behind
+        // the back of the undo history
+        let body_buffers = world.resource::<BodyBuffers>();
+        let ticks = world.resource::<SimulationControl>().ticks;
+        if let (SandState::Update, true, 1..) = (&self.state, body_buffers.active, ticks) {
+            let encoder = render_context.command_encoder();
+            encoder.clear_buffer(&body_buffers.claims, 0, None);
+            encoder.clear_buffer(&body_buffers.support, 0, None);
+            {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                pass.set_bind_group(0, &bind_groups.update[0], &[0]);
+                pass.set_bind_group(1, &bind_groups.bodies, &[]);
+                for id in [
+                    pipeline.stamp_bodies_pipeline,
+                    pipeline.place_displaced_pipeline,
+                ] {
+                    pass.set_pipeline(pipeline_cache.get_compute_pipeline(id).unwrap());
+                    pass.dispatch_workgroups(
+                        config.workgroups(width),
+                        config.workgroups(height),
+                        1,
+                    );
+                }
+            }
+            let cells = world.resource::<SandCells>();
+            encoder.copy_texture_to_texture(
+                cells.textures[1].as_image_copy(),
+                cells.textures[0].as_image_copy(),
+                cells.textures[0].size(),
+            );
+            // the last support is still on its way
+            if !body_buffers.is_pending() {
+                encoder.copy_buffer_to_buffer(
+                    &body_buffers.support,
+                    0,
+                    body_buffers.staging(),
+                    0,
+                    body_buffers.support.size(),
+                );
+                body_buffers.copied.store(true, Ordering::Relaxed);
+            }
+        }
 
         let mut pass = render_context
             .command_encoder()
             .begin_compute_pass(&ComputePassDescriptor::default());
 
-        pass.set_bind_group(0, texture_bind_group, &[]);
-
         // select the pipeline based on the current state
         match self.state {
-            SandState::Loading => {}
+            SandState::Loading => unreachable!(),
             SandState::Init => {
                 let init_pipeline = pipeline_cache
                     .get_compute_pipeline(pipeline.init_pipeline)
                     .unwrap();
                 pass.set_pipeline(init_pipeline);
-                pass.dispatch_workgroups(CONFIG.size.0 / CONFIG.workgroup_size, CONFIG.size.1 / CONFIG.workgroup_size, 1);
+                // init writes straight into cells[0]
+                pass.set_bind_group(0, &bind_groups.update[1], &[0]);
+                pass.dispatch_workgroups(config.workgroups(width), config.workgroups(height), 1);
             }
             SandState::Update => {
                 let update_pipeline = pipeline_cache
                     .get_compute_pipeline(pipeline.update_pipeline)
                     .unwrap();
-                pass.set_pipeline(update_pipeline);
-                pass.dispatch_workgroups(CONFIG.size.0 / CONFIG.workgroup_size, CONFIG.size.1 / CONFIG.workgroup_size, 1);
+                let reset_schedule_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.reset_schedule_pipeline)
+                    .unwrap();
+                let schedule_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.schedule_pipeline)
+                    .unwrap();
+                let tick_hash = world.get_resource::<TickHashBindGroup>().map(|bind_group| {
+                    let hash_pipeline = world.resource::<TickHashPipeline>().pipeline;
+                    (
+                        pipeline_cache.get_compute_pipeline(hash_pipeline).unwrap(),
+                        bind_group,
+                    )
+                });
+                for (index, offset) in steps.offsets.iter().enumerate() {
+                    // the first pass of every tick decides which chunks it and the second pass update
+                    if index % 2 == 0 {
+                        pass.set_bind_group(0, &bind_groups.update[0], &[*offset]);
+                        pass.set_bind_group(1, &bind_groups.schedule, &[]);
+                        pass.set_pipeline(reset_schedule_pipeline);
+                        pass.dispatch_workgroups(1, 1, 1);
+                        pass.set_pipeline(schedule_pipeline);
+                        pass.dispatch_workgroups(
+                            config.workgroups(chunks.size.x),
+                            config.workgroups(chunks.size.y),
+                            1,
+                        );
+                        pass.set_pipeline(update_pipeline);
+                    }
+                    pass.set_bind_group(0, &bind_groups.update[index % 2], &[*offset]);
+                    pass.dispatch_workgroups_indirect(&chunks.dispatch, 0);
+                    // the tick is done once the second pass wrote cells[0]
+                    if let (1, Some((hash_pipeline, hash_bind_group))) = (index % 2, tick_hash) {
+                        pass.set_pipeline(hash_pipeline);
+                        pass.set_bind_group(0, &hash_bind_group.0, &[*offset]);
+                        pass.dispatch_workgroups(
+                            config.workgroups(width),
+                            config.workgroups(height),
+                            1,
+                        );
+                        pass.set_pipeline(update_pipeline);
+                    }
+                }
+
+                // stamp the brush stroke into cells[0]
+                if let Some((_, size)) = world.resource::<BrushState>().bounds() {
+                    let paint_pipeline = pipeline_cache
+                        .get_compute_pipeline(pipeline.paint_pipeline)
+                        .unwrap();
+                    pass.set_pipeline(paint_pipeline);
+                    pass.set_bind_group(0, &bind_groups.update[1], &[0]);
+                    pass.dispatch_workgroups(
+                        config.workgroups(size.x),
+                        config.workgroups(size.y),
+                        1,
+                    );
+                }
             }
         }
 
+        let overlay = world.resource::<Overlay>();
+        let draw_pipeline = if overlay.heatmap {
+            pipeline.heatmap_pipeline
+        } else if overlay.shading {
+            pipeline.shaded_pipeline
+        } else {
+            pipeline.draw_pipeline
+        };
+        let draw_pipeline = pipeline_cache.get_compute_pipeline(draw_pipeline).unwrap();
+        pass.set_pipeline(draw_pipeline);
+        pass.set_bind_group(0, &bind_groups.draw, &[]);
+        pass.dispatch_workgroups(config.workgroups(width), config.workgroups(height), 1);
+
+        if overlay.chunks {
+            let chunks_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.chunks_pipeline)
+                .unwrap();
+            pass.set_pipeline(chunks_pipeline);
+            pass.dispatch_workgroups(config.workgroups(width), config.workgroups(height), 1);
+        }
+
         Ok(())
     }
 }
-
@@ -0,0 +1,30 @@
+use bevy::{prelude::*, render::extract_resource::*};
+
+// debug views drawn instead of or on top of the plain material colors
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct Overlay {
+    pub heatmap: bool,
+    // outlines the chunks that are being updated
+    pub chunks: bool,
+    // fake ambient occlusion and edge light, under the heatmap
+    pub shading: bool,
+}
+
+// h toggles the temperature heatmap, c the active chunks, l the shading
+pub fn update_overlay(mut overlay: ResMut<Overlay>, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(KeyCode::H) {
+        overlay.heatmap = !overlay.heatmap;
+        info!("heatmap {}", if overlay.heatmap { "on" } else { "off" });
+    }
+    if keys.just_pressed(KeyCode::C) {
+        overlay.chunks = !overlay.chunks;
+        info!(
+            "chunk overlay {}",
+            if overlay.chunks { "on" } else { "off" }
+        );
+    }
+    if keys.just_pressed(KeyCode::L) {
+        overlay.shading = !overlay.shading;
+        info!("shading {}", if overlay.shading { "on" } else { "off" });
+    }
+}
@@ -0,0 +1,60 @@
+use bevy::{
+    prelude::*,
+    render::{extract_resource::*, render_resource::*, renderer::*},
+};
+
+use crate::material::{MaterialKind, MaterialRegistry};
+
+#[derive(Clone, Copy, Default, Debug, ShaderType)]
+pub struct PaletteColor {
+    pub color: Vec4,
+    // how far the shade of a single grain may be off the color
+    pub variation: f32,
+    // light does not go through it, everything but gases
+    pub filled: u32,
+}
+
+// Display color of every material, indexed by material id. The draw pass only
+// looks at this, so what the world looks like can change without touching what
+// the simulation reads.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct SandPalette(pub Vec<PaletteColor>);
+
+impl SandPalette {
+    pub fn from_registry(registry: &MaterialRegistry) -> Self {
+        SandPalette(
+            registry
+                .materials
+                .iter()
+                .map(|material| PaletteColor {
+                    color: Vec4::from(material.color),
+                    variation: material.color_variation,
+                    filled: (material.kind != MaterialKind::Gas) as u32,
+                })
+                .collect(),
+        )
+    }
+}
+
+// the colors of the materials as they are (re)loaded
+pub fn update_palette(registry: Option<Res<MaterialRegistry>>, mut palette: ResMut<SandPalette>) {
+    if let Some(registry) = registry.filter(|registry| registry.is_changed()) {
+        *palette = SandPalette::from_registry(&registry);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PaletteBuffer(pub StorageBuffer<Vec<PaletteColor>>);
+
+pub fn prepare_palette(
+    palette: Res<SandPalette>,
+    mut palette_buffer: ResMut<PaletteBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !palette.is_changed() {
+        return;
+    }
+    palette_buffer.0.set(palette.0.clone());
+    palette_buffer.0.write_buffer(&render_device, &render_queue);
+}
@@ -5,11 +5,28 @@ use bevy::{
     render::{render_resource::*, renderer::*},
 };
 
+use crate::{
+    bodies::BODY_SLOTS, brush::GpuBrush, cells::CELL_FORMAT, config::SandConfig,
+    gravity::GpuGravity, scene::SceneMaterials, step::SandStep,
+};
+
 #[derive(Resource)]
 pub struct SandPipeline {
     pub texture_bind_group_layout: BindGroupLayout,
+    pub draw_bind_group_layout: BindGroupLayout,
+    pub bodies_bind_group_layout: BindGroupLayout,
+    pub schedule_bind_group_layout: BindGroupLayout,
     pub init_pipeline: CachedComputePipelineId,
     pub update_pipeline: CachedComputePipelineId,
+    pub paint_pipeline: CachedComputePipelineId,
+    pub reset_schedule_pipeline: CachedComputePipelineId,
+    pub schedule_pipeline: CachedComputePipelineId,
+    pub stamp_bodies_pipeline: CachedComputePipelineId,
+    pub place_displaced_pipeline: CachedComputePipelineId,
+    pub draw_pipeline: CachedComputePipelineId,
+    pub heatmap_pipeline: CachedComputePipelineId,
+    pub shaded_pipeline: CachedComputePipelineId,
+    pub chunks_pipeline: CachedComputePipelineId,
 }
 
 impl FromWorld for SandPipeline {
@@ -21,16 +38,26 @@ impl FromWorld for SandPipeline {
                     BindGroupLayoutEntry {
                         binding: 0,
                         visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::StorageTexture {
-                            access: StorageTextureAccess::ReadWrite,
-                            format: TextureFormat::Rgba8Unorm,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Uint,
                             view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
                         },
                         count: None,
                     },
                     BindGroupLayoutEntry {
                         binding: 1,
                         visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: CELL_FORMAT,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
                         ty: BindingType::Buffer {
                             ty: BufferBindingType::Uniform,
                             has_dynamic_offset: false,
@@ -38,32 +65,342 @@ impl FromWorld for SandPipeline {
                         },
                         count: None,
                     },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(SandStep::min_size()),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(GpuBrush::min_size()),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(GpuGravity::min_size()),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 11,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as u64),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 12,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(SceneMaterials::min_size()),
+                        },
+                        count: None,
+                    },
                 ],
             },
         );
+        let draw_bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Uint,
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::WriteOnly,
+                                format: TextureFormat::Rgba8Unorm,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let bodies_bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        // the update pass dispatches with the buffer, so it cannot be bound there
+        let schedule_bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                });
+        // the kernels are compiled for the configured workgroup size
+        let config = world.resource::<SandConfig>();
+        let shader_defs = vec![
+            ShaderDefVal::UInt("WORKGROUP_SIZE".into(), config.workgroup_size),
+            ShaderDefVal::UInt("BODY_SLOTS".into(), BODY_SLOTS),
+        ];
+        let scene = config.scene;
         let shader = world.resource::<AssetServer>().load("shaders/sand.wgsl");
+        let draw_shader = world.resource::<AssetServer>().load("shaders/draw.wgsl");
         let pipeline_cache = world.resource::<PipelineCache>();
         let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: None,
             layout: vec![texture_bind_group_layout.clone()],
             push_constant_ranges: Vec::new(),
             shader: shader.clone(),
-            shader_defs: vec![],
-            entry_point: Cow::from("init"),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from(scene.entry_point()),
         });
         let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: None,
             layout: vec![texture_bind_group_layout.clone()],
             push_constant_ranges: Vec::new(),
-            shader,
-            shader_defs: vec![],
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
             entry_point: Cow::from("update"),
         });
+        let paint_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![texture_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("paint"),
+        });
+        let reset_schedule_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![
+                    texture_bind_group_layout.clone(),
+                    schedule_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: Cow::from("resetSchedule"),
+            });
+        let schedule_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![
+                texture_bind_group_layout.clone(),
+                schedule_bind_group_layout.clone(),
+            ],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("schedule"),
+        });
+        let stamp_bodies_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![
+                    texture_bind_group_layout.clone(),
+                    bodies_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: Cow::from("stampBodies"),
+            });
+        let place_displaced_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: vec![
+                    texture_bind_group_layout.clone(),
+                    bodies_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader,
+                shader_defs: shader_defs.clone(),
+                entry_point: Cow::from("placeDisplaced"),
+            });
+        let draw_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![draw_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: draw_shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("draw"),
+        });
+        let heatmap_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![draw_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: draw_shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("drawHeatmap"),
+        });
+        let shaded_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![draw_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: draw_shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("drawShaded"),
+        });
+        let chunks_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![draw_bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: draw_shader,
+            shader_defs,
+            entry_point: Cow::from("drawChunks"),
+        });
 
         SandPipeline {
             texture_bind_group_layout,
+            draw_bind_group_layout,
+            bodies_bind_group_layout,
+            schedule_bind_group_layout,
             init_pipeline,
             update_pipeline,
+            paint_pipeline,
+            reset_schedule_pipeline,
+            schedule_pipeline,
+            stamp_bodies_pipeline,
+            place_displaced_pipeline,
+            draw_pipeline,
+            heatmap_pipeline,
+            shaded_pipeline,
+            chunks_pipeline,
         }
     }
 }
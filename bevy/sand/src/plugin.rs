@@ -1,13 +1,44 @@
 use bevy::{
+    diagnostic::FrameTimeDiagnosticsPlugin,
     prelude::*,
     render::{extract_resource::*, render_graph::*, render_resource::*, renderer::*, *},
 };
+use bevy_rapier2d::prelude::*;
 
 use crate::{
-    bind_group::queue_bind_group,
+    bind_group::prepare_bind_group,
+    bodies::{
+        apply_body_forces, collect_bodies, create_body_buffers, map_body_support, prepare_bodies,
+        spawn_bodies, spawn_walls, sync_physics_gravity, BodySupportReceiver, SandBodies,
+        PIXELS_PER_METER,
+    },
+    brush::{prepare_brush, update_brush, BrushBuffer, BrushState},
+    cells::create_cells,
+    chunks::create_chunks,
+    config::SandConfig,
+    control::{update_simulation_control, SimulationControl},
+    cpu::{run_cpu_sim, CpuSim, SandBackend},
+    gravity::{prepare_gravity, update_gravity, GravityBuffer, GravitySettings},
+    history::{apply_history, update_history, HistoryTextures, UndoHistory},
     image::SandImage,
+    material::{
+        apply_material_registry, load_material_registry, prepare_materials, MaterialBuffer,
+        MaterialRegistry, MaterialRegistryLoader,
+    },
     node::SandNode,
+    overlay::{update_overlay, Overlay},
+    palette::{prepare_palette, update_palette, PaletteBuffer, SandPalette},
     pipeline::SandPipeline,
+    scene::{prepare_scene, SceneBuffer},
+    snapshot::{
+        create_snapshot_readback, handle_snapshot_keys, load_snapshot, map_snapshot_readback,
+        queue_snapshot_saves, save_snapshots, SnapshotNode, SnapshotReceiver, SnapshotRequests,
+    },
+    stats::{
+        create_stats_readback, map_stats_readback, prepare_stats_bind_group, receive_stats,
+        setup_stats_hud, update_stats_hud, SandStats, StatsNode, StatsPipeline, StatsReceiver,
+    },
+    step::{prepare_steps, SandSteps},
     time::{prepare_time, ExtractedTime, TimeMeta},
 };
 
@@ -15,19 +46,98 @@ pub struct SandPlugin;
 
 impl Plugin for SandPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractResourcePlugin::<SandImage>::default())
+        // frame time for the stats HUD
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<RapierPhysicsPlugin<NoUserData>>() {
+            app.add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+                PIXELS_PER_METER,
+            ));
+        }
+        app.init_asset::<MaterialRegistry>()
+            .init_asset_loader::<MaterialRegistryLoader>()
+            .add_systems(Startup, load_material_registry)
+            .add_systems(Update, apply_material_registry)
+            .init_resource::<SandPalette>()
+            .add_systems(Update, update_palette.after(apply_material_registry))
+            .init_resource::<BrushState>()
+            .add_systems(Update, update_brush)
+            .init_resource::<SimulationControl>()
+            .add_systems(Update, update_simulation_control)
+            .init_resource::<GravitySettings>()
+            .add_systems(Update, update_gravity)
+            .init_resource::<Overlay>()
+            .add_systems(Update, update_overlay)
+            .init_resource::<SnapshotRequests>()
+            .add_systems(Update, handle_snapshot_keys)
+            .init_resource::<UndoHistory>()
+            .add_systems(
+                Update,
+                update_history
+                    .after(update_brush)
+                    .after(update_simulation_control)
+                    .after(handle_snapshot_keys),
+            )
+            .add_systems(
+                Update,
+                save_snapshots.run_if(resource_exists::<SnapshotReceiver>()),
+            )
+            .init_resource::<SandStats>()
+            .add_systems(Startup, setup_stats_hud)
+            .add_systems(
+                Update,
+                (
+                    receive_stats.run_if(resource_exists::<StatsReceiver>()),
+                    update_stats_hud,
+                )
+                    .chain(),
+            )
+            .init_resource::<SandBodies>()
+            .add_systems(Startup, spawn_walls)
+            .add_systems(
+                Update,
+                (
+                    spawn_bodies,
+                    sync_physics_gravity,
+                    apply_body_forces.run_if(resource_exists::<BodySupportReceiver>()),
+                ),
+            )
+            .add_systems(PostUpdate, collect_bodies.after(PhysicsSet::Writeback))
+            .add_plugins(ExtractResourcePlugin::<SandImage>::default())
+            .add_plugins(ExtractResourcePlugin::<BrushState>::default())
+            .add_plugins(ExtractResourcePlugin::<SimulationControl>::default())
+            .add_plugins(ExtractResourcePlugin::<MaterialRegistry>::default())
+            .add_plugins(ExtractResourcePlugin::<SandPalette>::default())
+            .add_plugins(ExtractResourcePlugin::<SnapshotRequests>::default())
+            .add_plugins(ExtractResourcePlugin::<UndoHistory>::default())
+            .add_plugins(ExtractResourcePlugin::<Overlay>::default())
+            .add_plugins(ExtractResourcePlugin::<GravitySettings>::default())
+            .add_plugins(ExtractResourcePlugin::<SandBodies>::default())
             .add_plugins(ExtractResourcePlugin::<ExtractedTime>::default());
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.add_systems(Render, queue_bind_group.in_set(RenderSet::Queue));
-        render_app.add_systems(Render, prepare_time.in_set(RenderSet::Prepare));
-
-        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
-        render_graph.add_node("sand_node", SandNode::default());
-        render_graph.add_node_edge("sand_node", bevy::render::main_graph::node::CAMERA_DRIVER);
     }
 
     fn finish(&self, app: &mut App) {
+        let backend = SandBackend::for_adapter(app.world.resource::<RenderAdapter>());
+        app.insert_resource(backend);
+        if backend == SandBackend::Cpu {
+            warn!(
+                "the adapter has no compute shaders, simulating on the CPU without snapshots, \
+                 undo or rigid bodies"
+            );
+            app.init_resource::<CpuSim>().add_systems(
+                Update,
+                run_cpu_sim
+                    .after(update_simulation_control)
+                    .after(update_brush)
+                    .after(update_gravity)
+                    .after(update_palette),
+            );
+            return;
+        }
+
         let render_device = app.world.resource::<RenderDevice>();
+        let config = app.world.resource::<SandConfig>().clone();
 
         let time_buffer = render_device.create_buffer(&BufferDescriptor {
             label: None,
@@ -35,12 +145,93 @@ impl Plugin for SandPlugin {
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let cells = create_cells(render_device, &config);
+        let chunks = create_chunks(render_device, &config);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let readback = create_snapshot_readback(render_device, config.size, sender);
+        let (stats_sender, stats_receiver) = crossbeam_channel::unbounded();
+        let stats_readback = create_stats_readback(render_device, stats_sender);
+        let (body_sender, body_receiver) = crossbeam_channel::unbounded();
+        let body_buffers = create_body_buffers(render_device, &config, body_sender);
+        app.insert_resource(SnapshotReceiver(receiver))
+            .insert_resource(StatsReceiver(stats_receiver))
+            .insert_resource(BodySupportReceiver(body_receiver));
 
         let render_app = app.sub_app_mut(RenderApp);
         render_app
+            .insert_resource(config)
             .init_resource::<SandPipeline>()
+            .init_resource::<SandSteps>()
+            .init_resource::<MaterialBuffer>()
+            .init_resource::<PaletteBuffer>()
+            .init_resource::<BrushBuffer>()
+            .init_resource::<GravityBuffer>()
+            .init_resource::<SceneBuffer>()
+            .init_resource::<HistoryTextures>()
+            .insert_resource(cells)
+            .insert_resource(chunks)
+            .insert_resource(readback)
+            .insert_resource(stats_readback)
+            .insert_resource(body_buffers)
+            .init_resource::<StatsPipeline>()
             .insert_resource(TimeMeta {
                 buffer: time_buffer,
             });
+        // nothing can be bound before the material registry has been loaded
+        render_app.add_systems(
+            Render,
+            prepare_bind_group
+                .in_set(RenderSet::PrepareBindGroups)
+                .run_if(resource_exists::<MaterialRegistry>()),
+        );
+        render_app.add_systems(Render, prepare_time.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(Render, prepare_steps.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(Render, prepare_brush.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(Render, prepare_gravity.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(
+            Render,
+            prepare_materials
+                .in_set(RenderSet::PrepareResources)
+                .run_if(resource_exists::<MaterialRegistry>()),
+        );
+        render_app.add_systems(
+            Render,
+            prepare_scene
+                .in_set(RenderSet::PrepareResources)
+                .run_if(resource_exists::<MaterialRegistry>()),
+        );
+        render_app.add_systems(
+            Render,
+            prepare_palette
+                .in_set(RenderSet::PrepareResources)
+                .run_if(resource_exists::<MaterialRegistry>()),
+        );
+        render_app.add_systems(Render, prepare_bodies.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(Render, map_body_support.in_set(RenderSet::Cleanup));
+        render_app.add_systems(Render, load_snapshot.in_set(RenderSet::PrepareResources));
+        render_app.add_systems(
+            Render,
+            apply_history
+                .in_set(RenderSet::PrepareResources)
+                .before(load_snapshot),
+        );
+        render_app.add_systems(
+            Render,
+            queue_snapshot_saves.in_set(RenderSet::PrepareResources),
+        );
+        render_app.add_systems(Render, map_snapshot_readback.in_set(RenderSet::Cleanup));
+        render_app.add_systems(
+            Render,
+            prepare_stats_bind_group.in_set(RenderSet::PrepareBindGroups),
+        );
+        render_app.add_systems(Render, map_stats_readback.in_set(RenderSet::Cleanup));
+
+        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+        render_graph.add_node("sand_node", SandNode::default());
+        render_graph.add_node_edge("sand_node", bevy::render::main_graph::node::CAMERA_DRIVER);
+        render_graph.add_node("sand_snapshot", SnapshotNode);
+        render_graph.add_node_edge("sand_node", "sand_snapshot");
+        render_graph.add_node("sand_stats", StatsNode);
+        render_graph.add_node_edge("sand_node", "sand_stats");
     }
 }
@@ -0,0 +1,604 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bevy::{
+    app::AppExit,
+    ecs::{event::ManualEventReader, system::SystemParam},
+    input::{
+        keyboard::KeyboardInput,
+        mouse::{MouseButtonInput, MouseScrollUnit, MouseWheel},
+        ButtonState, InputSystem,
+    },
+    prelude::*,
+    render::{render_graph, render_resource::*, renderer::*, Render, RenderApp, RenderSet},
+    time::TimeUpdateStrategy,
+    window::{PrimaryWindow, WindowFocused},
+};
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cells::SandCells,
+    config::SandConfig,
+    control::{SimulationControl, MAX_TICKS_PER_FRAME},
+    cpu::SandBackend,
+    step::{SandStep, SandSteps},
+};
+
+// every frame of a recorded or replayed session advances time by this much
+pub const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// the recording is written this often and when the app exits, so a crash only
+// loses the last few seconds of it
+const WRITE_EVERY_FRAMES: usize = 600;
+
+// one per tick of a frame, which the keyboard can not ask for more of
+const HASH_SLOTS: u32 = MAX_TICKS_PER_FRAME;
+const HASH_BYTES: u64 = HASH_SLOTS as u64 * 3 * 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum RecordedInput {
+    Key {
+        scan_code: u32,
+        key_code: Option<KeyCode>,
+        pressed: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    Wheel {
+        unit: MouseScrollUnit,
+        x: f32,
+        y: f32,
+    },
+    Focused(bool),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedFrame {
+    // seconds since the first recorded frame on the wall clock, only there to
+    // find your way around the log
+    pub time: f32,
+    // in the primary window, None while it is outside
+    pub cursor: Option<Vec2>,
+    // of the primary window in logical pixels, what the camera shows depends on
+    // it, zero in recordings from before it was recorded
+    #[serde(default)]
+    pub window_size: Vec2,
+    pub inputs: Vec<RecordedInput>,
+}
+
+// A session as it was played: the config with the seed and the scene, the
+// input of every frame since the first tick, and the hash of the world after
+// every tick to check a replay against.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recording {
+    pub config: SandConfig,
+    pub frames: Vec<RecordedFrame>,
+    pub hashes: Vec<(u32, u64)>,
+}
+
+impl Recording {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        ron::de::from_str(&text).with_context(|| format!("could not parse {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, text).with_context(|| format!("could not write {}", path.display()))
+    }
+}
+
+#[derive(Clone)]
+pub enum Session {
+    Interactive,
+    // recorded into the file every few seconds and when the app exits
+    Record(PathBuf),
+    Replay(Recording),
+}
+
+impl Session {
+    pub fn is_deterministic(&self) -> bool {
+        !matches!(self, Session::Interactive)
+    }
+}
+
+// Records or replays a session. Both run every frame FRAME_TIME apart and
+// without pipelined rendering, so the physics and the readbacks from the GPU
+// land on the same frames every time, and ignore all input until the first
+// tick has been hashed, so loading the shaders can take as long as it likes.
+//
+// Cells pushed aside by bodies race each other for empty cells on the GPU, so
+// sessions with bodies in them may not replay exactly.
+pub struct ReplayPlugin(pub Session);
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match &self.0 {
+            Session::Interactive => return,
+            Session::Record(path) => {
+                let config = app.world.resource::<SandConfig>().clone();
+                app.insert_resource(Recorder {
+                    path: path.clone(),
+                    recording: Recording {
+                        config,
+                        frames: Vec::new(),
+                        hashes: Vec::new(),
+                    },
+                    started: None,
+                    written: 0,
+                })
+                .add_systems(
+                    PreUpdate,
+                    record_inputs
+                        .before(InputSystem)
+                        .run_if(resource_exists::<TickHashReceiver>()),
+                )
+                .add_systems(
+                    Last,
+                    write_recording.run_if(resource_exists::<TickHashReceiver>()),
+                );
+            }
+            Session::Replay(recording) => {
+                app.insert_resource(Replayer {
+                    frames: recording.frames.clone(),
+                    expected: recording.hashes.iter().copied().collect(),
+                    frame: 0,
+                    running: false,
+                    done: false,
+                    checked: 0,
+                    mismatches: 0,
+                })
+                .add_systems(
+                    PreUpdate,
+                    replay_inputs
+                        .before(InputSystem)
+                        .run_if(resource_exists::<TickHashReceiver>()),
+                );
+            }
+        }
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME));
+    }
+
+    fn finish(&self, app: &mut App) {
+        if !self.0.is_deterministic() {
+            return;
+        }
+        // the ticks are hashed on the GPU, SandPlugin::finish has decided by now
+        if *app.world.resource::<SandBackend>() == SandBackend::Cpu {
+            warn!("recording and replaying need compute shaders, running without either");
+            return;
+        }
+        let render_device = app.world.resource::<RenderDevice>();
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("sand_tick_hashes"),
+            size: HASH_BYTES,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging = render_device.create_buffer(&BufferDescriptor {
+            label: Some("sand_tick_hashes_readback"),
+            size: HASH_BYTES,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        app.insert_resource(TickHashReceiver(receiver));
+        app.sub_app_mut(RenderApp)
+            .insert_resource(TickHashReadback {
+                buffer,
+                staging,
+                sender,
+            })
+            .init_resource::<TickHashPipeline>();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            prepare_tick_hash_bind_group.in_set(RenderSet::PrepareBindGroups),
+        );
+        render_app.add_systems(Render, read_tick_hashes.in_set(RenderSet::Cleanup));
+
+        let mut render_graph = render_app.world.resource_mut::<render_graph::RenderGraph>();
+        render_graph.add_node("sand_tick_hashes", TickHashNode);
+        render_graph.add_node_edge("sand_node", "sand_tick_hashes");
+    }
+}
+
+// the ticks hashed in a frame, in order
+#[derive(Resource)]
+pub struct TickHashReceiver(pub Receiver<Vec<(u32, u64)>>);
+
+#[derive(Resource)]
+struct Recorder {
+    path: PathBuf,
+    recording: Recording,
+    started: Option<Instant>,
+    // frames in the file
+    written: usize,
+}
+
+#[derive(Resource)]
+struct Replayer {
+    frames: Vec<RecordedFrame>,
+    expected: HashMap<u32, u64>,
+    // the next frame to replay
+    frame: usize,
+    // set once the first tick has been hashed
+    running: bool,
+    // set once all frames have been replayed, the input is the user's again
+    done: bool,
+    checked: usize,
+    mismatches: usize,
+}
+
+#[derive(Default)]
+struct InputReaders {
+    keys: ManualEventReader<KeyboardInput>,
+    buttons: ManualEventReader<MouseButtonInput>,
+    wheel: ManualEventReader<MouseWheel>,
+    focus: ManualEventReader<WindowFocused>,
+}
+
+#[derive(SystemParam)]
+struct InputEvents<'w> {
+    keys: ResMut<'w, Events<KeyboardInput>>,
+    buttons: ResMut<'w, Events<MouseButtonInput>>,
+    wheel: ResMut<'w, Events<MouseWheel>>,
+    focus: ResMut<'w, Events<WindowFocused>>,
+}
+
+fn is_pressed(state: ButtonState) -> bool {
+    state == ButtonState::Pressed
+}
+
+fn button_state(pressed: bool) -> ButtonState {
+    if pressed {
+        ButtonState::Pressed
+    } else {
+        ButtonState::Released
+    }
+}
+
+impl InputEvents<'_> {
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.buttons.clear();
+        self.wheel.clear();
+        self.focus.clear();
+    }
+
+    // the events sent since the readers last looked
+    fn read(&self, readers: &mut InputReaders) -> Vec<RecordedInput> {
+        let keys = readers
+            .keys
+            .read(&self.keys)
+            .map(|event| RecordedInput::Key {
+                scan_code: event.scan_code,
+                key_code: event.key_code,
+                pressed: is_pressed(event.state),
+            });
+        let buttons = readers
+            .buttons
+            .read(&self.buttons)
+            .map(|event| RecordedInput::MouseButton {
+                button: event.button,
+                pressed: is_pressed(event.state),
+            });
+        let wheel = readers
+            .wheel
+            .read(&self.wheel)
+            .map(|event| RecordedInput::Wheel {
+                unit: event.unit,
+                x: event.x,
+                y: event.y,
+            });
+        let focus = readers
+            .focus
+            .read(&self.focus)
+            .map(|event| RecordedInput::Focused(event.focused));
+        keys.chain(buttons).chain(wheel).chain(focus).collect()
+    }
+
+    fn send(&mut self, inputs: &[RecordedInput], window: Entity) {
+        for input in inputs {
+            match *input {
+                RecordedInput::Key {
+                    scan_code,
+                    key_code,
+                    pressed,
+                } => self.keys.send(KeyboardInput {
+                    scan_code,
+                    key_code,
+                    state: button_state(pressed),
+                    window,
+                }),
+                RecordedInput::MouseButton { button, pressed } => {
+                    self.buttons.send(MouseButtonInput {
+                        button,
+                        state: button_state(pressed),
+                        window,
+                    })
+                }
+                RecordedInput::Wheel { unit, x, y } => {
+                    self.wheel.send(MouseWheel { unit, x, y, window })
+                }
+                RecordedInput::Focused(focused) => {
+                    self.focus.send(WindowFocused { window, focused })
+                }
+            }
+        }
+    }
+}
+
+fn record_inputs(
+    mut recorder: ResMut<Recorder>,
+    receiver: Res<TickHashReceiver>,
+    mut events: InputEvents,
+    mut readers: Local<InputReaders>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+) {
+    recorder
+        .recording
+        .hashes
+        .extend(receiver.0.try_iter().flatten());
+    if recorder.recording.hashes.is_empty() {
+        events.clear();
+        return;
+    }
+
+    let Ok(window) = q_window.get_single() else {
+        return;
+    };
+    let started = *recorder.started.get_or_insert_with(Instant::now);
+    let frame = RecordedFrame {
+        time: started.elapsed().as_secs_f32(),
+        cursor: window.cursor_position(),
+        window_size: Vec2::new(window.width(), window.height()),
+        inputs: events.read(&mut readers),
+    };
+    recorder.recording.frames.push(frame);
+}
+
+fn write_recording(mut recorder: ResMut<Recorder>, mut exit: EventReader<AppExit>) {
+    let exiting = exit.read().next().is_some();
+    let frames = recorder.recording.frames.len();
+    if !exiting && frames < recorder.written + WRITE_EVERY_FRAMES {
+        return;
+    }
+    recorder.written = frames;
+    match recorder.recording.write(&recorder.path) {
+        Ok(()) if !exiting => {}
+        Ok(()) => info!(
+            "recorded {} frames to {}",
+            recorder.recording.frames.len(),
+            recorder.path.display()
+        ),
+        Err(err) => error!("{err:#}"),
+    }
+}
+
+fn replay_inputs(
+    mut replayer: ResMut<Replayer>,
+    receiver: Res<TickHashReceiver>,
+    mut events: InputEvents,
+    mut q_window: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+    mut control: ResMut<SimulationControl>,
+) {
+    let replayer = &mut *replayer;
+    for (tick, hash) in receiver.0.try_iter().flatten() {
+        replayer.running = true;
+        let Some(&expected) = replayer.expected.get(&tick) else {
+            continue;
+        };
+        if expected != hash {
+            if replayer.mismatches == 0 {
+                error!("tick {tick} does not match the recording");
+            }
+            replayer.mismatches += 1;
+        }
+        replayer.checked += 1;
+    }
+    if replayer.done {
+        return;
+    }
+    events.clear();
+    if !replayer.running {
+        return;
+    }
+
+    let Ok((window_entity, mut window)) = q_window.get_single_mut() else {
+        return;
+    };
+    if let Some(frame) = replayer.frames.get(replayer.frame) {
+        let size = Vec2::new(window.width(), window.height());
+        if frame.window_size != Vec2::ZERO && size != frame.window_size {
+            window
+                .resolution
+                .set(frame.window_size.x, frame.window_size.y);
+        }
+        window.set_cursor_position(frame.cursor);
+        events.send(&frame.inputs, window_entity);
+        replayer.frame += 1;
+        return;
+    }
+
+    // the hashes of the last frame came in this frame
+    replayer.done = true;
+    control.paused = true;
+    if replayer.mismatches == 0 {
+        info!(
+            "replay finished, {} of {} recorded ticks checked and all match",
+            replayer.checked,
+            replayer.expected.len()
+        );
+    } else {
+        error!(
+            "replay finished, {} of {} checked ticks do not match the recording",
+            replayer.mismatches, replayer.checked
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct TickHashPipeline {
+    layout: BindGroupLayout,
+    pub pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for TickHashPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(SandStep::min_size()),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(HASH_BYTES),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let config = world.resource::<SandConfig>();
+        let shader_defs = vec![
+            ShaderDefVal::UInt("WORKGROUP_SIZE".into(), config.workgroup_size),
+            ShaderDefVal::UInt("HASH_SLOTS".into(), HASH_SLOTS),
+        ];
+        let shader = world.resource::<AssetServer>().load("shaders/hash.wgsl");
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader,
+            shader_defs,
+            entry_point: Cow::from("hashCells"),
+        });
+        TickHashPipeline { layout, pipeline }
+    }
+}
+
+#[derive(Resource)]
+struct TickHashReadback {
+    // hashed into on the GPU after every tick
+    buffer: Buffer,
+    // a copy of `buffer` that gets mapped
+    staging: Buffer,
+    sender: Sender<Vec<(u32, u64)>>,
+}
+
+// bound by the SandNode with the offset of the second pass of every tick
+#[derive(Resource)]
+pub struct TickHashBindGroup(pub BindGroup);
+
+fn prepare_tick_hash_bind_group(
+    mut commands: Commands,
+    pipeline: Res<TickHashPipeline>,
+    cells: Res<SandCells>,
+    steps: Res<SandSteps>,
+    readback: Res<TickHashReadback>,
+    render_device: Res<RenderDevice>,
+) {
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&cells.views[0]),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: steps.buffer.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: readback.buffer.as_entire_binding(),
+            },
+        ],
+    );
+    commands.insert_resource(TickHashBindGroup(bind_group));
+}
+
+// waits for the GPU like read_frame_capture, the replay has to know about
+// every tick before the next frame
+fn read_tick_hashes(readback: Res<TickHashReadback>, render_device: Res<RenderDevice>) {
+    let slice = readback.staging.slice(..);
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result.is_ok());
+    });
+    render_device.poll(wgpu::Maintain::Wait);
+    if receiver.recv() != Ok(true) {
+        error!("could not map the tick hash buffer");
+        return;
+    }
+
+    let words: Vec<u32> = slice
+        .get_mapped_range()
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    readback.staging.unmap();
+    let mut hashes: Vec<(u32, u64)> = words
+        .chunks_exact(3)
+        .filter(|slot| slot[0] != 0)
+        .map(|slot| (slot[0] - 1, (slot[1] as u64) << 32 | slot[2] as u64))
+        .collect();
+    if !hashes.is_empty() {
+        hashes.sort_unstable();
+        let _ = readback.sender.send(hashes);
+    }
+}
+
+// hands the hashes of this frame to read_tick_hashes and starts over
+#[derive(Default)]
+struct TickHashNode;
+
+impl render_graph::Node for TickHashNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let readback = world.resource::<TickHashReadback>();
+        let encoder = render_context.command_encoder();
+        encoder.copy_buffer_to_buffer(&readback.buffer, 0, &readback.staging, 0, HASH_BYTES);
+        encoder.clear_buffer(&readback.buffer, 0, None);
+        Ok(())
+    }
+}
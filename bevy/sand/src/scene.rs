@@ -0,0 +1,65 @@
+use bevy::{
+    prelude::*,
+    render::{render_resource::*, renderer::*},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::material::MaterialRegistry;
+
+// The layouts a world can start from. Each one is a per-cell init kernel in
+// sand.wgsl, so adding a layout means adding a variant and its kernel.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SceneGenerator {
+    Empty,
+    // sand and water scattered at random
+    Noise,
+    Bricks,
+    #[default]
+    NoiseAndBricks,
+}
+
+impl SceneGenerator {
+    pub fn entry_point(self) -> &'static str {
+        match self {
+            SceneGenerator::Empty => "initEmpty",
+            SceneGenerator::Noise => "initNoise",
+            SceneGenerator::Bricks => "initBricks",
+            SceneGenerator::NoiseAndBricks => "initNoiseAndBricks",
+        }
+    }
+}
+
+// The ids of the materials the scenes are built from, looked up by name so
+// that they do not depend on the order of the registry. A material the
+// registry does not have is left out and its cells stay empty.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, ShaderType)]
+pub struct SceneMaterials {
+    pub sand: u32,
+    pub water: u32,
+    pub brick: u32,
+}
+
+impl SceneMaterials {
+    pub const NAMES: [&'static str; 3] = ["sand", "water", "brick"];
+
+    pub fn from_registry(registry: &MaterialRegistry) -> Self {
+        let [sand, water, brick] = Self::NAMES.map(|name| registry.id(name).unwrap_or(0));
+        SceneMaterials { sand, water, brick }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SceneBuffer(pub UniformBuffer<SceneMaterials>);
+
+pub fn prepare_scene(
+    registry: Res<MaterialRegistry>,
+    mut scene_buffer: ResMut<SceneBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if registry.is_changed() {
+        scene_buffer.0.set(SceneMaterials::from_registry(&registry));
+        scene_buffer.0.write_buffer(&render_device, &render_queue);
+    }
+}
@@ -0,0 +1,650 @@
+// A CPU model of the init and update kernels in sand.wgsl. It applies the same
+// rules in the same order and draws the same random numbers, so starting from
+// the same cells and seed it produces exactly the grid the GPU does, tick for
+// tick. Keep the two in sync when changing either. cpu.rs runs it in place of
+// the GPU where there are no compute shaders.
+//
+// It also skips the chunks the GPU skips (see chunks.rs). Copying cells between
+// textures has nothing to model here, as the grid is updated in place.
+
+use bevy::math::{IVec2, UVec2};
+
+use crate::{
+    config::SandConfig,
+    gravity::GravitySettings,
+    material::{GpuMaterial, MaterialKind, MaterialRegistry, AMBIENT_TEMPERATURE, NO_MATERIAL},
+    scene::{SceneGenerator, SceneMaterials},
+};
+
+// cell flags
+pub const MOVED: u32 = 1;
+
+// anything not in the registry, like the cells outside the world, never moves
+const OUTSIDE: u32 = u32::MAX;
+
+// the only material wind blows things into
+const EMPTY: u32 = 0;
+
+// see `isBrick` in sand.wgsl
+const BRICK_SIZE: IVec2 = IVec2::new(200, 100);
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Cell {
+    pub material: u32,
+    pub flags: u32,
+    pub temperature: f32,
+    // what the color of the grain varies by, only read when drawing
+    pub seed: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sim {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u32,
+    pub tick: u32,
+    // row by row, y grows downwards
+    pub cells: Vec<Cell>,
+    pub gravity: GravitySettings,
+    materials: Vec<GpuMaterial>,
+    scene: SceneMaterials,
+    // side of a chunk in blocks and chunks per row and column
+    chunk_size: u32,
+    chunks: UVec2,
+    // the chunks the last tick changed, which the next one updates along with
+    // their neighbours
+    changed: Vec<bool>,
+}
+
+// the same as `hash` in random.wgsl, so both backends draw the same numbers
+pub fn hash(value: u32) -> u32 {
+    let mut state = value;
+    state ^= 2747636419;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state.wrapping_mul(2654435769)
+}
+
+impl Sim {
+    // a world full of material 0, of the size and seed of the config
+    pub fn new(config: &SandConfig, registry: &MaterialRegistry) -> Self {
+        let (width, height) = config.size;
+        // the blocks of the odd phase reach one cell past the edge
+        let chunks = UVec2::new(
+            config.workgroups(width / 2 + 1),
+            config.workgroups(height / 2 + 1),
+        );
+        let mut sim = Sim {
+            width,
+            height,
+            seed: config.seed,
+            tick: 0,
+            cells: Vec::new(),
+            gravity: GravitySettings::default(),
+            materials: registry.gpu_materials(),
+            scene: SceneMaterials::from_registry(registry),
+            chunk_size: config.workgroup_size,
+            chunks,
+            changed: Vec::new(),
+        };
+        sim.cells = vec![sim.new_cell(0); (width * height) as usize];
+        sim.wake_all();
+        sim
+    }
+
+    // a cell of the material at the temperature it starts at
+    pub fn new_cell(&self, material: u32) -> Cell {
+        let temperature = self
+            .materials
+            .get(material as usize)
+            .map_or(AMBIENT_TEMPERATURE, |material| material.temperature);
+        Cell {
+            material,
+            flags: 0,
+            temperature,
+            seed: 0,
+        }
+    }
+
+    // see `newGrain` in sand.wgsl
+    pub fn new_grain(&self, material: u32, x: i32, y: i32, tick: u32) -> Cell {
+        Cell {
+            seed: hash(hash(self.seed ^ tick) ^ hash(x as u32 ^ hash(y as u32))),
+            ..self.new_cell(material)
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let in_bounds = x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32;
+        in_bounds.then(|| (y as u32 * self.width + x as u32) as usize)
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<Cell> {
+        self.index(x, y).map(|index| self.cells[index])
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, material: u32) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index] = self.new_grain(material, x, y, self.tick);
+            self.wake_chunk(x, y);
+        }
+    }
+
+    // Like `set`, but the cell counts as moved, see `paint` in sand.wgsl. That
+    // runs with the step of the first tick of the frame, which is the tick the
+    // seed of the grain is made from.
+    pub fn paint(&mut self, x: i32, y: i32, material: u32, tick: u32) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index] = Cell {
+                flags: MOVED,
+                ..self.new_grain(material, x, y, tick)
+            };
+            self.wake_chunk(x, y);
+        }
+    }
+
+    // for when every cell may start moving, like after gravity turned
+    pub fn wake_all(&mut self) {
+        self.changed = vec![true; (self.chunks.x * self.chunks.y) as usize];
+    }
+
+    fn chunk_index(&self, chunk: UVec2) -> usize {
+        (chunk.y * self.chunks.x + chunk.x) as usize
+    }
+
+    // see `wakeChunk` in sand.wgsl
+    fn wake_chunk(&mut self, x: i32, y: i32) {
+        let chunk = UVec2::new(x as u32, y as u32) / 2 / self.chunk_size;
+        let index = self.chunk_index(chunk);
+        self.changed[index] = true;
+    }
+
+    // for when the registry changed, the cells keep their ids
+    pub fn set_materials(&mut self, registry: &MaterialRegistry) {
+        self.materials = registry.gpu_materials();
+        self.scene = SceneMaterials::from_registry(registry);
+    }
+
+    // see the init kernels in sand.wgsl
+    pub fn generate(&mut self, scene: SceneGenerator) {
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let material = match scene {
+                    SceneGenerator::Empty => EMPTY,
+                    SceneGenerator::Noise => self.noise(x, y),
+                    SceneGenerator::Bricks if self.is_brick(x, y) => self.scene.brick,
+                    SceneGenerator::Bricks => EMPTY,
+                    SceneGenerator::NoiseAndBricks if self.is_brick(x, y) => self.scene.brick,
+                    SceneGenerator::NoiseAndBricks => self.noise(x, y),
+                };
+                self.set(x, y, material);
+            }
+        }
+    }
+
+    fn scene_random(&self, x: i32, y: i32, salt: u32) -> f32 {
+        let value = hash(self.seed ^ hash(salt ^ hash(x as u32 ^ hash(y as u32))));
+        value as f32 / 4294967295.0
+    }
+
+    fn noise(&self, x: i32, y: i32) -> u32 {
+        if self.scene_random(x, y, 1) > 0.9 {
+            self.scene.water
+        } else if self.scene_random(x, y, 2) > 0.9 {
+            self.scene.sand
+        } else {
+            EMPTY
+        }
+    }
+
+    fn is_brick(&self, x: i32, y: i32) -> bool {
+        let row = y / BRICK_SIZE.y;
+        if row % 3 != 0 {
+            return false;
+        }
+        let column = x / BRICK_SIZE.x;
+        ((column - 1).max(0)..=column + 1).any(|candidate| {
+            if candidate % 2 != row % 2 || self.scene_random(candidate, row, 3) <= 0.4 {
+                return false;
+            }
+            let shift = ((0.5 - self.scene_random(candidate, row, 4)) * 100.0) as i32;
+            let x = x - shift;
+            x >= 0 && x / BRICK_SIZE.x == candidate && x < self.width as i32
+        })
+    }
+
+    // how many cells of every material there are, indexed by id
+    pub fn counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.materials.len()];
+        for cell in &self.cells {
+            if let Some(count) = counts.get_mut(cell.material as usize) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    // One tick is two passes, the second one on the grid offset by one cell.
+    // Returns the cells the passes moved, like the moves counter on the GPU.
+    pub fn step(&mut self) -> u32 {
+        let updated = self.schedule();
+        let mut changed = vec![false; updated.len()];
+        let moves = self.pass(0, &updated, &mut changed) + self.pass(1, &updated, &mut changed);
+        self.changed = changed;
+        self.tick = self.tick.wrapping_add(1);
+        moves
+    }
+
+    // see `schedule` in sand.wgsl, the chunks next to one that changed
+    fn schedule(&self) -> Vec<bool> {
+        let mut updated = vec![false; self.changed.len()];
+        for y in 0..self.chunks.y {
+            for x in 0..self.chunks.x {
+                if !self.changed[self.chunk_index(UVec2::new(x, y))] {
+                    continue;
+                }
+                for near_y in y.saturating_sub(1)..(y + 2).min(self.chunks.y) {
+                    for near_x in x.saturating_sub(1)..(x + 2).min(self.chunks.x) {
+                        updated[self.chunk_index(UVec2::new(near_x, near_y))] = true;
+                    }
+                }
+            }
+        }
+        updated
+    }
+
+    fn pass(&mut self, phase: u32, updated: &[bool], changed: &mut [bool]) -> u32 {
+        // the blocks are disjoint, so the order they are visited in does not
+        // matter and the grid can be updated in place
+        let blocks = UVec2::new(self.width / 2 + 1, self.height / 2 + 1);
+        let mut moves = 0;
+        for chunk_y in 0..self.chunks.y {
+            for chunk_x in 0..self.chunks.x {
+                let chunk = self.chunk_index(UVec2::new(chunk_x, chunk_y));
+                if !updated[chunk] {
+                    continue;
+                }
+                let first = UVec2::new(chunk_x, chunk_y) * self.chunk_size;
+                let last = (first + self.chunk_size).min(blocks);
+                for block_y in first.y..last.y {
+                    for block_x in first.x..last.x {
+                        let (awake, moved) = self.update_block(block_x, block_y, phase);
+                        changed[chunk] |= awake;
+                        moves += moved;
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    fn material(&self, cell: Cell) -> Option<&GpuMaterial> {
+        self.materials.get(cell.material as usize)
+    }
+
+    fn is_movable(&self, cell: Cell) -> bool {
+        self.material(cell)
+            .is_some_and(|material| material.kind != MaterialKind::Static as u32)
+    }
+
+    fn is_fluid(&self, cell: Cell) -> bool {
+        self.is_movable(cell)
+            && self.material(cell).is_some_and(|material| {
+                material.kind == MaterialKind::Liquid as u32
+                    || material.kind == MaterialKind::Gas as u32
+            })
+    }
+
+    fn both_gases(&self, a: Cell, b: Cell) -> bool {
+        let is_gas = |cell| {
+            self.is_fluid(cell)
+                && self.materials[cell.material as usize].kind == MaterialKind::Gas as u32
+        };
+        is_gas(a) && is_gas(b)
+    }
+
+    fn sinks(&self, upper: Cell, lower: Cell) -> bool {
+        self.is_movable(upper)
+            && self.is_movable(lower)
+            && self.materials[upper.material as usize].density
+                > self.materials[lower.material as usize].density
+    }
+
+    fn spreads(&self, a: Cell, b: Cell, chance: f32) -> bool {
+        if !self.is_fluid(a) || !self.is_fluid(b) || a.material == b.material {
+            return false;
+        }
+        let dispersion = f32::max(
+            self.materials[a.material as usize].dispersion,
+            self.materials[b.material as usize].dispersion,
+        );
+        chance < dispersion
+    }
+
+    fn conductivity(&self, cell: Cell) -> f32 {
+        self.material(cell)
+            .map_or(0.0, |material| material.conductivity)
+    }
+
+    fn heat_flow(&self, a: Cell, b: Cell) -> f32 {
+        (a.temperature - b.temperature)
+            * f32::min(self.conductivity(a), self.conductivity(b))
+            * 0.25
+    }
+
+    // see `exchangeHeat` in sand.wgsl
+    fn exchange_heat(&self, cells: &mut [Cell; 4]) {
+        let top = self.heat_flow(cells[0], cells[1]);
+        let bottom = self.heat_flow(cells[2], cells[3]);
+        let left = self.heat_flow(cells[0], cells[2]);
+        let right = self.heat_flow(cells[1], cells[3]);
+        cells[0].temperature -= top + left;
+        cells[1].temperature += top - right;
+        cells[2].temperature += left - bottom;
+        cells[3].temperature += bottom + right;
+    }
+
+    // see `blow` in sand.wgsl
+    fn blows(&self, cells: &[Cell; 4], a: usize, b: usize, wind: f32, chance: f32) -> bool {
+        if (cells[a].flags | cells[b].flags) & MOVED != 0 {
+            return false;
+        }
+        let (upwind, downwind) = if wind > 0.0 { (a, b) } else { (b, a) };
+        let carried = cells[upwind];
+        if !self.is_movable(carried)
+            || carried.material == EMPTY
+            || cells[downwind].material != EMPTY
+        {
+            return false;
+        }
+        chance < wind.abs() / self.materials[carried.material as usize].density
+    }
+
+    // see `react` in sand.wgsl
+    fn react(&self, cell: Cell, random: u32) -> Cell {
+        let Some(material) = self.material(cell) else {
+            return cell;
+        };
+        let mut result = cell;
+        if material.heat_source != 0 {
+            result.temperature = material.temperature;
+        }
+        let into = if material.above_into != NO_MATERIAL
+            && cell.temperature > material.above_temperature
+        {
+            material.above_into
+        } else if material.below_into != NO_MATERIAL
+            && cell.temperature < material.below_temperature
+        {
+            material.below_into
+        } else if material.decay_into != NO_MATERIAL
+            && (random & 0xffff) as f32 / 65536.0 < material.decay_chance
+        {
+            material.decay_into
+        } else {
+            NO_MATERIAL
+        };
+        if into != NO_MATERIAL {
+            result.material = into;
+            result.flags |= MOVED;
+        }
+        result
+    }
+
+    fn block_random(&self, block_x: u32, block_y: u32, phase: u32) -> u32 {
+        let pass_index = self.tick.wrapping_mul(2).wrapping_add(phase);
+        hash(self.seed ^ hash(pass_index ^ hash(block_x ^ hash(block_y))))
+    }
+
+    // see `keepsAwake` in sand.wgsl
+    fn keeps_awake(&self, before: Cell, after: Cell) -> bool {
+        after.flags & MOVED != 0
+            || (after.temperature - before.temperature).abs() > 0.1
+            || self
+                .material(after)
+                .is_some_and(|material| material.decay_into != NO_MATERIAL)
+    }
+
+    // see `mayTrade` in sand.wgsl
+    fn may_trade(&self, a: Cell, b: Cell, wind: f32) -> bool {
+        if self.is_fluid(a) && self.is_fluid(b) && a.material != b.material {
+            let dispersion = f32::max(
+                self.materials[a.material as usize].dispersion,
+                self.materials[b.material as usize].dispersion,
+            );
+            if dispersion > 0.0 {
+                return true;
+            }
+        }
+        let (upwind, downwind) = if wind < 0.0 { (b, a) } else { (a, b) };
+        wind != 0.0
+            && self.is_movable(upwind)
+            && upwind.material != EMPTY
+            && downwind.material == EMPTY
+    }
+
+    // see `updateBlock` in sand.wgsl, whether the block keeps its chunk awake
+    // and how many of its cells moved
+    //
+    //   0 1
+    //   2 3   (towards gravity)
+    fn update_block(&mut self, block_x: u32, block_y: u32, phase: u32) -> (bool, u32) {
+        let origin = IVec2::new(block_x as i32, block_y as i32) * 2 - phase as i32;
+        let direction = self.gravity.direction;
+        let locations = [0, 1, 2, 3].map(|index| {
+            let location = origin + direction.block_offset(index);
+            (location.x, location.y)
+        });
+        let mut cells = locations.map(|(x, y)| match self.get(x, y) {
+            Some(cell) => Cell {
+                flags: cell.flags & !MOVED,
+                ..cell
+            },
+            None => Cell {
+                material: OUTSIDE,
+                ..Cell::default()
+            },
+        });
+        let loaded = cells;
+        let random = self.block_random(block_x, block_y, phase);
+
+        let swap = |cells: &mut [Cell; 4], a: usize, b: usize| {
+            cells.swap(a, b);
+            cells[a].flags |= MOVED;
+            cells[b].flags |= MOVED;
+        };
+
+        let left_falls = self.sinks(cells[0], cells[2]);
+        let right_falls = self.sinks(cells[1], cells[3]);
+        if left_falls {
+            swap(&mut cells, 0, 2);
+        }
+        if right_falls {
+            swap(&mut cells, 1, 3);
+        }
+
+        let mut slides = false;
+        if !left_falls && !right_falls {
+            if self.sinks(cells[0], cells[3]) {
+                swap(&mut cells, 0, 3);
+                slides = true;
+            } else if self.sinks(cells[1], cells[2]) {
+                swap(&mut cells, 1, 2);
+                slides = true;
+            }
+        }
+
+        let resting = !left_falls && !right_falls && !slides;
+        if (resting || self.both_gases(cells[0], cells[1]))
+            && self.spreads(cells[0], cells[1], (random & 0xffff) as f32 / 65536.0)
+        {
+            swap(&mut cells, 0, 1);
+        }
+        if (resting || self.both_gases(cells[2], cells[3]))
+            && self.spreads(cells[2], cells[3], (random >> 16) as f32 / 65536.0)
+        {
+            swap(&mut cells, 2, 3);
+        }
+
+        let wind = self
+            .gravity
+            .wind_at(origin)
+            .dot(direction.lateral().as_vec2());
+        let gust = hash(random ^ 4);
+        if self.blows(&cells, 0, 1, wind, (gust & 0xffff) as f32 / 65536.0) {
+            swap(&mut cells, 0, 1);
+        }
+        if self.blows(&cells, 2, 3, wind, (gust >> 16) as f32 / 65536.0) {
+            swap(&mut cells, 2, 3);
+        }
+
+        self.exchange_heat(&mut cells);
+        let mut awake = false;
+        let mut moved = 0;
+        for (i, (x, y)) in locations.into_iter().enumerate() {
+            cells[i] = self.react(cells[i], hash(random ^ i as u32));
+            if let Some(index) = self.index(x, y) {
+                awake |= self.keeps_awake(loaded[i], cells[i]);
+                moved += cells[i].flags & MOVED;
+                self.cells[index] = cells[i];
+            }
+        }
+        awake |=
+            self.may_trade(cells[0], cells[1], wind) || self.may_trade(cells[2], cells[3], wind);
+        (awake, moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> MaterialRegistry {
+        ron::de::from_str(include_str!("../assets/sand.materials.ron")).unwrap()
+    }
+
+    fn config(size: (u32, u32), seed: u32) -> SandConfig {
+        SandConfig {
+            size,
+            seed,
+            ..SandConfig::default()
+        }
+    }
+
+    fn run(sim: &mut Sim, ticks: u32) {
+        for _ in 0..ticks {
+            sim.step();
+        }
+    }
+
+    #[test]
+    fn sand_falls_to_the_bottom() {
+        let mut sim = Sim::new(&config((8, 8), 1), &registry());
+        let sand = sim.scene.sand;
+        sim.set(3, 0, sand);
+        run(&mut sim, 8);
+        assert_eq!(sim.get(3, 7).unwrap().material, sand);
+        assert_eq!(sim.counts()[sand as usize], 1);
+    }
+
+    #[test]
+    fn sand_piles_up_on_bricks() {
+        let mut sim = Sim::new(&config((8, 8), 1), &registry());
+        let SceneMaterials { sand, brick, .. } = sim.scene;
+        for x in 0..8 {
+            sim.set(x, 4, brick);
+        }
+        sim.set(3, 0, sand);
+        run(&mut sim, 8);
+        assert_eq!(sim.get(3, 3).unwrap().material, sand);
+        assert_eq!(sim.get(3, 4).unwrap().material, brick);
+    }
+
+    #[test]
+    fn settled_chunks_sleep_until_painted() {
+        let mut sim = Sim::new(&config((64, 16), 1), &registry());
+        let SceneMaterials { sand, water, .. } = sim.scene;
+        for x in 0..64 {
+            sim.set(x, 0, sand);
+        }
+        run(&mut sim, 20);
+        assert!(sim.changed.iter().all(|changed| !changed));
+        assert!((0..64).all(|x| sim.get(x, 15).unwrap().material == sand));
+
+        sim.paint(40, 0, water, sim.tick);
+        run(&mut sim, 20);
+        assert!((0..64).any(|x| sim.get(x, 14).unwrap().material == water));
+    }
+
+    #[test]
+    fn liquids_level_out() {
+        let mut sim = Sim::new(&config((16, 8), 1), &registry());
+        let water = sim.scene.water;
+        for y in 0..8 {
+            sim.set(0, y, water);
+            sim.set(1, y, water);
+        }
+        run(&mut sim, 1000);
+        for x in 0..16 {
+            assert_eq!(sim.get(x, 7).unwrap().material, water, "column {x}");
+        }
+    }
+
+    #[test]
+    fn counts_are_conserved() {
+        let mut sim = Sim::new(&config((64, 64), 7), &registry());
+        let SceneMaterials { sand, water, brick } = sim.scene;
+        sim.generate(SceneGenerator::Noise);
+        for x in 8..56 {
+            sim.set(x, 40, brick);
+        }
+        let counts = sim.counts();
+        assert!(counts[sand as usize] > 0 && counts[water as usize] > 0);
+        run(&mut sim, 200);
+        assert_eq!(sim.counts(), counts);
+    }
+
+    #[test]
+    fn grains_keep_their_seed_as_they_fall() {
+        let mut sim = Sim::new(&config((8, 8), 1), &registry());
+        let sand = sim.scene.sand;
+        sim.set(3, 0, sand);
+        let seed = sim.get(3, 0).unwrap().seed;
+        run(&mut sim, 8);
+        assert_eq!(sim.get(3, 7).unwrap().seed, seed);
+        assert_ne!(sim.get(3, 6).unwrap().seed, seed);
+    }
+
+    #[test]
+    fn scenes_are_built_the_same_from_a_seed() {
+        let registry = registry();
+        let mut a = Sim::new(&config((1000, 400), 3), &registry);
+        let mut b = Sim::new(&config((1000, 400), 3), &registry);
+        a.generate(SceneGenerator::NoiseAndBricks);
+        b.generate(SceneGenerator::NoiseAndBricks);
+        assert_eq!(a.cells, b.cells);
+        assert!(a.counts()[a.scene.brick as usize] > 0);
+    }
+
+    #[test]
+    fn scenes_are_built_from_materials_by_name() {
+        let registry = registry();
+        let mut reordered = registry.clone();
+        reordered.materials.swap(1, 2);
+        reordered.materials.swap(2, 3);
+        let mut a = Sim::new(&config((1000, 400), 3), &registry);
+        let mut b = Sim::new(&config((1000, 400), 3), &reordered);
+        a.generate(SceneGenerator::NoiseAndBricks);
+        b.generate(SceneGenerator::NoiseAndBricks);
+        let names = |sim: &Sim, registry: &MaterialRegistry| -> Vec<String> {
+            sim.cells
+                .iter()
+                .map(|cell| registry.materials[cell.material as usize].name.clone())
+                .collect()
+        };
+        assert_ne!(a.scene, b.scene);
+        assert_eq!(names(&a, &registry), names(&b, &reordered));
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use bevy::{
+    prelude::*,
+    render::{extract_resource::*, render_graph, render_resource::*, renderer::*},
+};
+use crossbeam_channel::{Receiver, Sender};
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+use crate::{
+    cells::{SandCells, CELL_CHANNELS},
+    chunks::SandChunks,
+    config::SandConfig,
+    material::MaterialRegistry,
+    sim::hash,
+};
+
+// the channels of every cell as in CELL_FORMAT, row by row
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<u32>,
+}
+
+// set for exactly one frame
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct SnapshotRequests {
+    pub save: Option<PathBuf>,
+    pub load: Option<Arc<Snapshot>>,
+}
+
+#[derive(Resource)]
+pub struct SnapshotReceiver(pub Receiver<(PathBuf, Snapshot)>);
+
+// F5 saves the world to the snapshot path of the config and F9 loads it back
+pub fn handle_snapshot_keys(
+    mut requests: ResMut<SnapshotRequests>,
+    keys: Res<Input<KeyCode>>,
+    registry: Option<Res<MaterialRegistry>>,
+    config: Res<SandConfig>,
+) {
+    requests.save = None;
+    requests.load = None;
+
+    let path = &config.snapshot_path;
+    if keys.just_pressed(KeyCode::F5) {
+        requests.save = Some(path.clone());
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        let Some(registry) = registry else {
+            return;
+        };
+        match read_snapshot(path, &registry) {
+            Ok(snapshot) if (snapshot.width, snapshot.height) == config.size => {
+                info!("loaded snapshot from {}", path.display());
+                requests.load = Some(Arc::new(snapshot));
+            }
+            Ok(snapshot) => error!(
+                "snapshot {} is {}x{}, the world is {}x{}",
+                path.display(),
+                snapshot.width,
+                snapshot.height,
+                config.size.0,
+                config.size.1
+            ),
+            Err(err) => error!("could not load {}: {err:#}", path.display()),
+        }
+    }
+}
+
+pub fn save_snapshots(receiver: Res<SnapshotReceiver>) {
+    for (path, snapshot) in receiver.0.try_iter() {
+        match write_snapshot(&path, &snapshot) {
+            Ok(()) => info!("saved snapshot to {}", path.display()),
+            Err(err) => error!("could not save {}: {err:#}", path.display()),
+        }
+    }
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+// A cell of the material at the given temperature, or the one the material
+// starts at. Snapshots keep no grain seeds, the cells get new ones from their
+// index.
+fn new_cell(
+    index: usize,
+    id: u32,
+    flags: u32,
+    temperature: Option<f32>,
+    registry: &MaterialRegistry,
+) -> anyhow::Result<[u32; CELL_CHANNELS]> {
+    let Some(material) = registry.materials.get(id as usize) else {
+        bail!("material id {id} is not in the registry");
+    };
+    let temperature = temperature.unwrap_or(material.temperature);
+    Ok([id, flags, temperature.to_bits(), hash(index as u32)])
+}
+
+pub fn write_snapshot(path: &Path, snapshot: &Snapshot) -> anyhow::Result<()> {
+    if is_png(path) {
+        encode_png(snapshot)?.save(path)?;
+    } else {
+        fs::write(path, encode_binary(snapshot)?)?;
+    }
+    Ok(())
+}
+
+pub fn read_snapshot(path: &Path, registry: &MaterialRegistry) -> anyhow::Result<Snapshot> {
+    if is_png(path) {
+        let DynamicImage::ImageRgba16(image) = image::open(path)? else {
+            bail!("not a 16 bit RGBA image");
+        };
+        decode_png(&image, registry)
+    } else {
+        decode_binary(&fs::read(path)?, registry)
+    }
+}
+
+// PNG snapshots are 16 bit RGBA images holding the cells exactly: the material
+// id in red, the flags in green and the bits of the temperature in blue (high
+// half) and alpha (low half). They are for keeping scenes in a format other
+// tools can open, not for looking at.
+pub type PngSnapshot = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+pub fn encode_png(snapshot: &Snapshot) -> anyhow::Result<PngSnapshot> {
+    let pixels = snapshot
+        .cells
+        .chunks_exact(CELL_CHANNELS)
+        .map(|cell| {
+            let id = u16::try_from(cell[0]).context("material id does not fit in 16 bits")?;
+            let flags = u16::try_from(cell[1]).context("cell flags do not fit in 16 bits")?;
+            Ok([id, flags, (cell[2] >> 16) as u16, cell[2] as u16])
+        })
+        .collect::<anyhow::Result<Vec<[u16; 4]>>>()?;
+    PngSnapshot::from_raw(snapshot.width, snapshot.height, pixels.concat())
+        .context("snapshot does not match its size")
+}
+
+pub fn decode_png(image: &PngSnapshot, registry: &MaterialRegistry) -> anyhow::Result<Snapshot> {
+    let cells = image
+        .pixels()
+        .enumerate()
+        .map(|(index, &Rgba([id, flags, high, low]))| {
+            let temperature = f32::from_bits((high as u32) << 16 | low as u32);
+            new_cell(index, id as u32, flags as u32, Some(temperature), registry)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Snapshot {
+        width: image.width(),
+        height: image.height(),
+        cells: cells.concat(),
+    })
+}
+
+// Binary snapshots are a header followed by tagged layers, so more per-cell
+// data can be added later. Every layer is stored row by row.
+//
+//   "SAND" version:u32 width:u32 height:u32 layer_count:u32
+//   per layer: tag:[u8; 4] length:u32 data:[u8; length]
+//
+// All numbers are little endian. The layers are "MATL", one byte of material id
+// per cell, and the optional "TEMP", the temperature of every cell as f32.
+// Without it cells start at the temperature of their material. The flags of
+// the cells are not kept, they only say what happened in the last tick.
+const MAGIC: &[u8; 4] = b"SAND";
+const VERSION: u32 = 1;
+const MATERIAL_LAYER: &[u8; 4] = b"MATL";
+const TEMPERATURE_LAYER: &[u8; 4] = b"TEMP";
+
+pub fn encode_binary(snapshot: &Snapshot) -> anyhow::Result<Vec<u8>> {
+    let materials = snapshot
+        .cells
+        .chunks_exact(CELL_CHANNELS)
+        .map(|cell| u8::try_from(cell[0]).context("material id does not fit in a byte"))
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+    let temperatures: Vec<u8> = snapshot
+        .cells
+        .chunks_exact(CELL_CHANNELS)
+        .flat_map(|cell| cell[2].to_le_bytes())
+        .collect();
+
+    let mut bytes = Vec::with_capacity(36 + materials.len() + temperatures.len());
+    bytes.extend_from_slice(MAGIC);
+    for value in [VERSION, snapshot.width, snapshot.height, 2] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for (tag, layer) in [
+        (MATERIAL_LAYER, &materials),
+        (TEMPERATURE_LAYER, &temperatures),
+    ] {
+        bytes.extend_from_slice(tag);
+        bytes.extend_from_slice(&(layer.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(layer);
+    }
+    Ok(bytes)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < count {
+            bail!("unexpected end of file");
+        }
+        let (head, tail) = self.0.split_at(count);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+pub fn decode_binary(bytes: &[u8], registry: &MaterialRegistry) -> anyhow::Result<Snapshot> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != MAGIC {
+        bail!("not a sand snapshot");
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        bail!("unsupported snapshot version {version}");
+    }
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let layer_count = reader.u32()?;
+
+    let mut materials = None;
+    let mut temperatures = None;
+    for _ in 0..layer_count {
+        let tag = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let data = reader.take(length)?;
+        if tag == MATERIAL_LAYER {
+            materials = Some(data);
+        } else if tag == TEMPERATURE_LAYER {
+            temperatures = Some(data);
+        }
+    }
+
+    let materials = materials.context("snapshot has no material layer")?;
+    let Some(cell_count) = (width as usize).checked_mul(height as usize) else {
+        bail!("snapshot is too large");
+    };
+    if materials.len() != cell_count {
+        bail!("material layer does not match the size of the snapshot");
+    }
+    let temperatures: Vec<Option<f32>> = match temperatures {
+        Some(data) if data.len() == materials.len() * 4 => data
+            .chunks_exact(4)
+            .map(|bytes| Some(f32::from_le_bytes(bytes.try_into().unwrap())))
+            .collect(),
+        Some(_) => bail!("temperature layer does not match the size of the snapshot"),
+        None => vec![None; materials.len()],
+    };
+    let cells = materials
+        .iter()
+        .zip(temperatures)
+        .enumerate()
+        .map(|(index, (&id, temperature))| new_cell(index, id as u32, 0, temperature, registry))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Snapshot {
+        width,
+        height,
+        cells: cells.concat(),
+    })
+}
+
+const CELL_BYTES: u32 = CELL_CHANNELS as u32 * 4;
+
+#[derive(Resource)]
+pub struct SnapshotReadback {
+    buffer: Buffer,
+    size: (u32, u32),
+    padded_bytes_per_row: u32,
+    // saves waiting for the buffer, the SnapshotNode copies for the first one
+    // as soon as nothing is pending
+    queued: VecDeque<PathBuf>,
+    // file the buffer is being mapped for, and where the mapping result arrives
+    pending: Option<(PathBuf, Receiver<bool>)>,
+    sender: Sender<(PathBuf, Snapshot)>,
+}
+
+pub fn create_snapshot_readback(
+    render_device: &RenderDevice,
+    size: (u32, u32),
+    sender: Sender<(PathBuf, Snapshot)>,
+) -> SnapshotReadback {
+    let padded_bytes_per_row =
+        RenderDevice::align_copy_bytes_per_row((size.0 * CELL_BYTES) as usize) as u32;
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_snapshot_readback"),
+        size: (padded_bytes_per_row * size.1) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    SnapshotReadback {
+        buffer,
+        size,
+        padded_bytes_per_row,
+        queued: VecDeque::new(),
+        pending: None,
+        sender,
+    }
+}
+
+// A save asked for while an earlier one is still being read back waits for it
+// and gets the world as it is once the buffer is free.
+pub fn queue_snapshot_saves(
+    mut readback: ResMut<SnapshotReadback>,
+    requests: Res<SnapshotRequests>,
+) {
+    if let Some(path) = &requests.save {
+        readback.queued.push_back(path.clone());
+    }
+}
+
+pub fn load_snapshot(
+    requests: Res<SnapshotRequests>,
+    cells: Res<SandCells>,
+    chunks: Res<SandChunks>,
+    render_queue: Res<RenderQueue>,
+) {
+    if let Some(snapshot) = &requests.load {
+        chunks.wake_all(&render_queue);
+        render_queue.write_texture(
+            cells.textures[0].as_image_copy(),
+            bevy::core::cast_slice(&snapshot.cells),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(snapshot.width * CELL_BYTES),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: snapshot.width,
+                height: snapshot.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+// runs after the frame has been submitted, so the copy recorded by the
+// SnapshotNode is on its way when the buffer gets mapped
+pub fn map_snapshot_readback(mut readback: ResMut<SnapshotReadback>) {
+    if let Some((path, mapped)) = &readback.pending {
+        let Ok(success) = mapped.try_recv() else {
+            return;
+        };
+        if success {
+            let row_bytes = (readback.size.0 * CELL_BYTES) as usize;
+            let cells = readback
+                .buffer
+                .slice(..)
+                .get_mapped_range()
+                .chunks_exact(readback.padded_bytes_per_row as usize)
+                .flat_map(|row| row[..row_bytes].chunks_exact(4))
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            let snapshot = Snapshot {
+                width: readback.size.0,
+                height: readback.size.1,
+                cells,
+            };
+            let _ = readback.sender.send((path.clone(), snapshot));
+        } else {
+            error!("could not map the snapshot buffer");
+        }
+        readback.buffer.unmap();
+        readback.pending = None;
+    } else if let Some(path) = readback.queued.pop_front() {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        readback
+            .buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result.is_ok());
+            });
+        readback.pending = Some((path, receiver));
+    }
+}
+
+#[derive(Default)]
+pub struct SnapshotNode;
+
+impl render_graph::Node for SnapshotNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let readback = world.resource::<SnapshotReadback>();
+        if readback.queued.is_empty() || readback.pending.is_some() {
+            return Ok(());
+        }
+
+        let cells = world.resource::<SandCells>();
+        render_context.command_encoder().copy_texture_to_buffer(
+            cells.textures[0].as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &readback.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(readback.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: readback.size.0,
+                height: readback.size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> MaterialRegistry {
+        ron::de::from_str(
+            r#"(materials: [
+                (name: "empty", kind: Gas, density: 1.0, dispersion: 0.0, color: (0.0, 0.0, 0.0, 0.0), color_variation: 0.0),
+                (name: "sand", kind: Powder, density: 3.0, dispersion: 0.0, color: (1.0, 1.0, 0.0, 1.0), color_variation: 0.1),
+                (name: "lava", kind: Liquid, density: 2.5, dispersion: 0.2, color: (1.0, 0.3, 0.0, 1.0), color_variation: 0.1, temperature: 1200.0),
+            ])"#,
+        )
+        .unwrap()
+    }
+
+    fn snapshot() -> Snapshot {
+        let cells = [
+            (0, 20.0),
+            (1, 20.0),
+            (2, 1200.0),
+            (1, -5.5),
+            (0, 300.0),
+            (2, 950.0),
+        ];
+        Snapshot {
+            width: 3,
+            height: 2,
+            cells: cells
+                .iter()
+                .flat_map(|&(id, temperature): &(u32, f32)| [id, 0, temperature.to_bits(), 0])
+                .collect(),
+        }
+    }
+
+    // the seeds of the grains are not saved
+    fn without_seeds(cells: &[u32]) -> Vec<u32> {
+        cells
+            .chunks_exact(CELL_CHANNELS)
+            .flat_map(|cell| [cell[0], cell[1], cell[2]])
+            .collect()
+    }
+
+    #[test]
+    fn binary_snapshots_round_trip() {
+        let snapshot = snapshot();
+        let bytes = encode_binary(&snapshot).unwrap();
+        let decoded = decode_binary(&bytes, &registry()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(
+            without_seeds(&decoded.cells),
+            without_seeds(&snapshot.cells)
+        );
+    }
+
+    #[test]
+    fn png_snapshots_keep_every_cell() {
+        let mut snapshot = snapshot();
+        // a moved cell, and one only the bits of its temperature tell apart
+        snapshot.cells[1] = 1;
+        snapshot.cells[CELL_CHANNELS + 2] = 20.000002f32.to_bits();
+        let image = encode_png(&snapshot).unwrap();
+        let decoded = decode_png(&image, &registry()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(
+            without_seeds(&decoded.cells),
+            without_seeds(&snapshot.cells)
+        );
+    }
+
+    #[test]
+    fn unknown_materials_are_rejected() {
+        let mut snapshot = snapshot();
+        snapshot.cells[CELL_CHANNELS] = 3;
+        let bytes = encode_binary(&snapshot).unwrap();
+        let err = decode_binary(&bytes, &registry()).err().unwrap();
+        assert_eq!(err.to_string(), "material id 3 is not in the registry");
+        let image = encode_png(&snapshot).unwrap();
+        assert!(decode_png(&image, &registry()).is_err());
+    }
+
+    #[test]
+    fn oversized_snapshots_are_rejected() {
+        let mut bytes = encode_binary(&snapshot()).unwrap();
+        bytes[8..16].copy_from_slice(&[0xff; 8]);
+        assert!(decode_binary(&bytes, &registry()).is_err());
+    }
+
+    #[test]
+    fn cells_without_a_temperature_start_at_their_materials() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        for value in [VERSION, 2, 1, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(MATERIAL_LAYER);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 2]);
+        let decoded = decode_binary(&bytes, &registry()).unwrap();
+        assert_eq!(
+            without_seeds(&decoded.cells),
+            [1, 0, 20.0f32.to_bits(), 2, 0, 1200.0f32.to_bits()]
+        );
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let bytes = encode_binary(&snapshot()).unwrap();
+        for length in [0, 3, 12, bytes.len() - 1] {
+            assert!(decode_binary(&bytes[..length], &registry()).is_err());
+        }
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let mut bytes = encode_binary(&snapshot()).unwrap();
+        bytes[..4].copy_from_slice(b"PNG\0");
+        let err = decode_binary(&bytes, &registry()).err().unwrap();
+        assert_eq!(err.to_string(), "not a sand snapshot");
+    }
+}
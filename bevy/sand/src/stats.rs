@@ -0,0 +1,361 @@
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    render::{render_graph, render_resource::*, renderer::*},
+};
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{cells::SandCells, config::SandConfig, material::MaterialRegistry, sim::Sim};
+
+// materials the statistics tell apart, the ones past them are counted as the last
+pub const STATS_MATERIALS: u32 = 64;
+// the moved count comes first, copied from the counter of the update passes
+const STATS_SLOTS: u32 = STATS_MATERIALS + 1;
+const STATS_BYTES: u64 = STATS_SLOTS as u64 * 4;
+
+// What the world looked like a frame or two ago, counted on the GPU and read
+// back without stalling the frame.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct SandStats {
+    // cells per material id
+    pub counts: Vec<u32>,
+    // cells moved by the update passes of a frame, twice if both passes of a
+    // tick moved them
+    pub moved: u32,
+}
+
+impl SandStats {
+    // what the CPU backend shows, `moved` is what its ticks of the frame returned
+    pub fn from_sim(sim: &Sim, moved: u32) -> Self {
+        SandStats {
+            counts: sim.counts().into_iter().map(|count| count as u32).collect(),
+            moved,
+        }
+    }
+
+    // the stats buffer as read back, one little endian u32 per slot with the
+    // moved count first
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut slots = bytes
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+        SandStats {
+            moved: slots.next().unwrap_or(0),
+            counts: slots.collect(),
+        }
+    }
+
+    // every cell is counted once, so this is the size of the world
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&count| count as u64).sum()
+    }
+
+    pub fn count(&self, id: u32) -> u32 {
+        self.counts.get(id as usize).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Resource)]
+pub struct StatsReceiver(pub Receiver<SandStats>);
+
+pub fn receive_stats(receiver: Res<StatsReceiver>, mut stats: ResMut<SandStats>) {
+    if let Some(latest) = receiver.0.try_iter().last() {
+        *stats = latest;
+    }
+}
+
+#[derive(Component)]
+pub struct StatsText;
+
+pub fn setup_stats_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+        StatsText,
+    ));
+}
+
+pub fn update_stats_hud(
+    stats: Res<SandStats>,
+    registry: Option<Res<MaterialRegistry>>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut texts: Query<&mut Text, With<StatsText>>,
+) {
+    let frame_time = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+        .unwrap_or(0.0);
+    let mut lines = vec![
+        format!("frame {frame_time:.1} ms"),
+        format!("cells {}", stats.total()),
+        format!("moved {}", stats.moved),
+    ];
+    for (id, &count) in stats.counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let name = registry
+            .as_ref()
+            .and_then(|registry| registry.materials.get(id))
+            .map_or_else(|| format!("#{id}"), |material| material.name.clone());
+        lines.push(format!("{name} {count}"));
+    }
+
+    for mut text in &mut texts {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+#[derive(Resource)]
+pub struct StatsPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for StatsPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(STATS_BYTES),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let config = world.resource::<SandConfig>();
+        let shader_defs = vec![
+            ShaderDefVal::UInt("WORKGROUP_SIZE".into(), config.workgroup_size),
+            ShaderDefVal::UInt("STATS_SLOTS".into(), STATS_SLOTS),
+        ];
+        let shader = world.resource::<AssetServer>().load("shaders/stats.wgsl");
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: None,
+            layout: vec![layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader,
+            shader_defs,
+            entry_point: Cow::from("countCells"),
+        });
+        StatsPipeline { layout, pipeline }
+    }
+}
+
+#[derive(Resource)]
+pub struct StatsReadback {
+    // counted into on the GPU
+    buffer: Buffer,
+    // counted into by the update passes, taken and cleared by the StatsNode
+    pub moves: Buffer,
+    // a copy of `buffer` that gets mapped
+    staging: Buffer,
+    // set by the StatsNode when it recorded a copy into `staging` this frame
+    copied: AtomicBool,
+    pending: Option<Receiver<bool>>,
+    sender: Sender<SandStats>,
+}
+
+pub fn create_stats_readback(
+    render_device: &RenderDevice,
+    sender: Sender<SandStats>,
+) -> StatsReadback {
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_stats"),
+        size: STATS_BYTES,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let moves = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_stats_moves"),
+        size: std::mem::size_of::<u32>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let staging = render_device.create_buffer(&BufferDescriptor {
+        label: Some("sand_stats_readback"),
+        size: STATS_BYTES,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    StatsReadback {
+        buffer,
+        moves,
+        staging,
+        copied: AtomicBool::new(false),
+        pending: None,
+        sender,
+    }
+}
+
+#[derive(Resource)]
+pub struct StatsBindGroup(BindGroup);
+
+pub fn prepare_stats_bind_group(
+    mut commands: Commands,
+    pipeline: Res<StatsPipeline>,
+    cells: Res<SandCells>,
+    readback: Res<StatsReadback>,
+    render_device: Res<RenderDevice>,
+) {
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&cells.views[0]),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: readback.buffer.as_entire_binding(),
+            },
+        ],
+    );
+    commands.insert_resource(StatsBindGroup(bind_group));
+}
+
+// like map_snapshot_readback, but for every frame the StatsNode counted in
+pub fn map_stats_readback(mut readback: ResMut<StatsReadback>) {
+    if let Some(mapped) = &readback.pending {
+        let Ok(success) = mapped.try_recv() else {
+            return;
+        };
+        if success {
+            let stats = SandStats::from_bytes(&readback.staging.slice(..).get_mapped_range());
+            let _ = readback.sender.send(stats);
+        } else {
+            error!("could not map the stats buffer");
+        }
+        readback.staging.unmap();
+        readback.pending = None;
+    } else if readback.copied.swap(false, Ordering::Relaxed) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        readback
+            .staging
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result.is_ok());
+            });
+        readback.pending = Some(receiver);
+    }
+}
+
+#[derive(Default)]
+pub struct StatsNode;
+
+impl render_graph::Node for StatsNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let readback = world.resource::<StatsReadback>();
+        let encoder = render_context.command_encoder();
+        // the moves of a frame are only ever counted for that frame
+        let counted = count_cells(world, encoder, readback);
+        encoder.clear_buffer(&readback.moves, 0, None);
+        if counted {
+            readback.copied.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+// records counting cells[0] into the stats buffer and copying it to staging,
+// unless the last counts are still on their way
+fn count_cells(world: &World, encoder: &mut CommandEncoder, readback: &StatsReadback) -> bool {
+    if readback.pending.is_some() {
+        return false;
+    }
+    let pipeline = world.resource::<StatsPipeline>();
+    let Some(count_pipeline) = world
+        .resource::<PipelineCache>()
+        .get_compute_pipeline(pipeline.pipeline)
+    else {
+        return false;
+    };
+    let Some(bind_group) = world.get_resource::<StatsBindGroup>() else {
+        return false;
+    };
+    let config = world.resource::<SandConfig>();
+
+    encoder.clear_buffer(&readback.buffer, 0, None);
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_pipeline(count_pipeline);
+        pass.set_bind_group(0, &bind_group.0, &[]);
+        pass.dispatch_workgroups(
+            config.workgroups(config.size.0),
+            config.workgroups(config.size.1),
+            1,
+        );
+    }
+    // slot 0 is the moved count
+    encoder.copy_buffer_to_buffer(&readback.moves, 0, &readback.buffer, 0, 4);
+    encoder.copy_buffer_to_buffer(&readback.buffer, 0, &readback.staging, 0, STATS_BYTES);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_moved_count_comes_first() {
+        let bytes: Vec<u8> = [7u32, 100, 20, 0, 3]
+            .iter()
+            .flat_map(|slot| slot.to_le_bytes())
+            .collect();
+        let stats = SandStats::from_bytes(&bytes);
+        assert_eq!(stats.moved, 7);
+        assert_eq!(stats.counts, [100, 20, 0, 3]);
+        assert_eq!(stats.count(1), 20);
+        assert_eq!(stats.count(9), 0);
+        assert_eq!(stats.total(), 123);
+    }
+
+    #[test]
+    fn counts_above_a_byte_are_read_whole() {
+        let stats = SandStats::from_bytes(&[0, 1, 0, 0, 0x10, 0x32, 0x54, 0x76]);
+        assert_eq!(stats.moved, 256);
+        assert_eq!(stats.counts, [0x7654_3210]);
+    }
+}
@@ -0,0 +1,56 @@
+use bevy::{
+    prelude::*,
+    render::{render_resource::*, renderer::*},
+};
+
+use crate::{config::SandConfig, control::SimulationControl};
+
+// One entry per dispatched update pass. A tick is two passes: the Margolus
+// grid is offset by one cell on the second one.
+#[derive(Clone, Copy, ShaderType)]
+pub struct SandStep {
+    pub seed: u32,
+    pub tick: u32,
+    pub phase: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct SandSteps {
+    pub buffer: DynamicUniformBuffer<SandStep>,
+    pub offsets: Vec<u32>,
+    // number of ticks actually dispatched, advanced by the node
+    pub tick: u32,
+    // set by the node once the init kernel has been dispatched
+    pub initialized: bool,
+}
+
+pub fn prepare_steps(
+    mut steps: ResMut<SandSteps>,
+    control: Res<SimulationControl>,
+    config: Res<SandConfig>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let steps = &mut *steps;
+    steps.buffer.clear();
+    steps.offsets.clear();
+    for tick in steps.tick..steps.tick + control.ticks {
+        for phase in 0..2 {
+            let offset = steps.buffer.push(SandStep {
+                seed: config.seed,
+                tick,
+                phase,
+            });
+            steps.offsets.push(offset);
+        }
+    }
+    // init and paint bind the buffer at offset 0 even when no tick runs
+    if steps.offsets.is_empty() {
+        steps.buffer.push(SandStep {
+            seed: config.seed,
+            tick: steps.tick,
+            phase: 0,
+        });
+    }
+    steps.buffer.write_buffer(&render_device, &render_queue);
+}
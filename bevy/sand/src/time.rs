@@ -1,9 +1,8 @@
 use bevy::{
     prelude::*,
-    render::{render_resource::*, extract_resource::*, renderer::*},
+    render::{extract_resource::*, render_resource::*, renderer::*},
 };
 
-
 #[derive(Resource, Default)]
 pub struct ExtractedTime {
     seconds_since_startup: f32,
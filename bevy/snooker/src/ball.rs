@@ -2,18 +2,25 @@ use bevy::{
     prelude::*,
     render::{extract_resource::ExtractResource, render_resource::Buffer, renderer::RenderQueue}, core::{Pod, Zeroable}, sprite::MaterialMesh2dBundle,
 };
-use bevy_rapier3d::prelude::{RigidBody, Collider};
-use rand::random;
 
-use crate::{camera::MainCamera, config::CONFIG, selection::Selection};
+use crate::{
+    camera::MainCamera,
+    config::CONFIG,
+    game::Game,
+    physics::ball_physics,
+    selection::Selection,
+};
 
 #[repr(C)]
-#[derive(Pod, Copy, Clone, Default)]
+#[derive(Copy, Clone, Default)]
 pub struct BallStatus {
     position: [f32; 3],
     selected: i32
 }
 
+// a vec3 and an i32, 16 bytes without padding
+unsafe impl Pod for BallStatus {}
+
 unsafe impl Zeroable for BallStatus {
     fn zeroed() -> Self {
         BallStatus {
@@ -37,6 +44,13 @@ impl ExtractResource for BallPositions {
 #[derive(Component)]
 pub struct Ball;
 
+// which ball it is to the rules of the game, see rules/
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BallId(pub u8);
+
+// the band around a striped pool ball is drawn as a white middle
+const STRIPE_RADIUS: f32 = 0.6;
+
 #[derive(Resource)]
 pub struct BallBuffer(pub Buffer);
 
@@ -72,23 +86,35 @@ pub fn setup_balls(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    game: Res<Game>,
 ) {
-    for _ in 0..CONFIG.number_of_balls {
-        let mut position = Vec3::new(random::<f32>() - 0.5, random::<f32>() - 0.5, 0.) * 500.;
-        position.z = 20.;
+    for (id, position) in game.rack() {
+        let position = position.extend(20.);
 
-        commands
-            .spawn(RigidBody::Fixed)
-            .insert(Collider::ball(CONFIG.ball_radius))
+        let mut ball = commands.spawn(ball_physics());
+        ball
             .insert(MaterialMesh2dBundle {
                 mesh: meshes
                     .add(shape::Circle::new(CONFIG.ball_radius).into())
                     .into(),
-                material: materials.add(ColorMaterial::from(Color::RED)),
+                material: materials.add(ColorMaterial::from(game.ball_color(id))),
                 transform: Transform::from_translation(position),
                 ..default()
             })
             .insert(Ball)
+            .insert(BallId(id))
             .insert(Selection { selected: false});
+        if game.is_striped(id) {
+            ball.with_children(|ball| {
+                ball.spawn(MaterialMesh2dBundle {
+                    mesh: meshes
+                        .add(shape::Circle::new(CONFIG.ball_radius * STRIPE_RADIUS).into())
+                        .into(),
+                    material: materials.add(ColorMaterial::from(Color::WHITE)),
+                    transform: Transform::from_xyz(0., 0., 0.1),
+                    ..default()
+                });
+            });
+        }
     }
 }
@@ -11,6 +11,7 @@ use crate::{
 #[derive(Resource)]
 pub struct GpuComputeBindGroup(pub BindGroup);
 
+#[allow(clippy::too_many_arguments)]
 pub fn queue_bind_group(
     mut commands: Commands,
     pipeline: Res<GpuComputePipeline>,
@@ -7,7 +7,7 @@ pub const BALL_BUFFER_SIZE: u64 = ((std::mem::size_of::<[f32; 3]>() + std::mem::
                                     as u64;
 
 pub const POCKET_BUFFER_SIZE: u64 = ((std::mem::size_of::<[f32; 3]>() + std::mem::size_of::<i32>())
-                                    * 6 as usize)
+                                    * 6)
                                     as u64;
 
 pub const CUE_BALL_BUFFER_SIZE: u64 = (std::mem::size_of::<f32>() * 2) as u64;
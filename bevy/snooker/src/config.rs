@@ -4,20 +4,47 @@ pub struct Config {
     pub table_size: IVec2,
     pub workgroup_size: u32,
     pub ball_radius: f32,
-    pub cue_ball_starting_position: Vec3,
+    // the most object balls a game racks, what the GPU ball buffer holds
     pub number_of_balls: i32,
     pub wall_width: f32,
     pub wall_color: Color,
-    pub pocket_radius: f32
+    pub pocket_radius: f32,
+    // the table is 3.57m long between the cushions
+    pub pixels_per_meter: f32,
+    pub ball_restitution: f32,
+    pub cushion_restitution: f32,
+    // between two balls and between a ball and a cushion, what turns side
+    // spin into throw
+    pub ball_friction: f32,
+    pub cushion_friction: f32,
+    // of the cloth, while a ball slides over it, rolls on it and spins in place
+    pub sliding_friction: f32,
+    pub rolling_friction: f32,
+    pub spinning_friction: f32,
+    pub ball_mass: f32,
+    // of the cue ball after a full power shot, in pixels per second
+    pub max_shot_speed: f32,
+    // how far from the middle of the cue ball the tip may hit it, in radii
+    pub max_tip_offset: f32,
 }
 
 pub const CONFIG: Config = Config {
     table_size: IVec2::new(1280, 1280 / 2),
     workgroup_size: 8,
     ball_radius: 10.,
-    cue_ball_starting_position: Vec3::new(0.0, 0.0, 20.0),
-    number_of_balls: 10,
+    number_of_balls: 21,
     wall_width: 20.0,
     wall_color: Color::TEAL,
-    pocket_radius: 12.
+    pocket_radius: 30.,
+    pixels_per_meter: 1240. / 3.57,
+    ball_restitution: 0.95,
+    cushion_restitution: 0.75,
+    ball_friction: 0.05,
+    cushion_friction: 0.2,
+    sliding_friction: 0.2,
+    rolling_friction: 0.015,
+    spinning_friction: 0.04,
+    ball_mass: 0.14,
+    max_shot_speed: 5. * 1240. / 3.57,
+    max_tip_offset: 0.5,
 };
@@ -0,0 +1,273 @@
+use bevy::{prelude::*, sprite::Anchor};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    ball::Ball,
+    config::CONFIG,
+    cue_ball::CueBall,
+    cursor::Cursor,
+    physics::{is_at_rest, Spin},
+    pocket::Potted,
+};
+
+// how far the cursor has to be dragged back for a full power shot
+const MAX_DRAG: f32 = 200.0;
+const CUE_LENGTH: f32 = 300.0;
+const CUE_WIDTH: f32 = 5.0;
+const CUE_COLOR: Color = Color::rgb(0.6, 0.4, 0.2);
+// between the tip and the cue ball, and how far back a full power shot pulls the cue
+const CUE_GAP: f32 = 4.0;
+const MAX_PULL: f32 = 60.0;
+const METER_SIZE: Vec2 = Vec2::new(200.0, 10.0);
+const SPIN_INDICATOR_RADIUS: f32 = 8.0;
+// how far the object ball's path is drawn after the first contact
+const PREVIEW_LENGTH: f32 = 100.0;
+// each press of w, a, s or d moves the tip this far across the cue ball
+const TIP_STEP: f32 = 0.25;
+
+type CueBallOnTable = (With<CueBall>, Without<Potted>);
+
+#[derive(Component)]
+pub struct CueStick;
+
+#[derive(Component)]
+pub struct PowerMeter;
+
+// sent when the cue strikes the cue ball
+#[derive(Event)]
+pub struct ShotPlayed;
+
+#[derive(Resource)]
+pub struct Shot {
+    // from the cue ball towards where it is sent
+    pub aim: Vec2,
+    // of CONFIG.max_shot_speed
+    pub power: f32,
+    // where the tip hits the cue ball as seen from behind, within the unit
+    // circle of CONFIG.max_tip_offset
+    pub tip: Vec2,
+    // the balls have stopped and the cue can be played
+    pub ready: bool,
+    // where the cursor was when the cue was drawn back
+    drag_start: Option<Vec2>,
+}
+
+impl Default for Shot {
+    fn default() -> Self {
+        Shot {
+            aim: Vec2::X,
+            power: 0.0,
+            tip: Vec2::ZERO,
+            ready: false,
+            drag_start: None,
+        }
+    }
+}
+
+// the meter sits in the top cushion, the spin indicator right of it
+fn meter_position() -> Vec2 {
+    Vec2::new(
+        80.0 - CONFIG.table_size.x as f32 / 2.0,
+        (CONFIG.table_size.y as f32 - CONFIG.wall_width) / 2.0,
+    )
+}
+
+fn spin_indicator_position() -> Vec2 {
+    meter_position() + Vec2::new(METER_SIZE.x + 40.0, 0.0)
+}
+
+pub fn setup_cue(mut commands: Commands) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: CUE_COLOR,
+                custom_size: Some(Vec2::new(CUE_LENGTH, CUE_WIDTH)),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        CueStick,
+    ));
+
+    let position = meter_position();
+    commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: Color::BLACK,
+            custom_size: Some(METER_SIZE),
+            anchor: Anchor::CenterLeft,
+            ..default()
+        },
+        transform: Transform::from_translation(position.extend(30.0)),
+        ..default()
+    });
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::ORANGE_RED,
+                custom_size: Some(Vec2::new(0.0, METER_SIZE.y)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform: Transform::from_translation(position.extend(31.0)),
+            ..default()
+        },
+        PowerMeter,
+    ));
+}
+
+// Once every ball has stopped the cue follows the cursor around the cue ball.
+// Pressing the left mouse button and dragging back sets the power, releasing
+// plays the shot. W and s move the tip up and down for top and back spin, a and
+// d sideways for side spin.
+pub fn play_cue(
+    mut shot: ResMut<Shot>,
+    mut cue_ball: Query<
+        (&Transform, &mut Velocity, &mut Spin, &mut ExternalImpulse),
+        CueBallOnTable,
+    >,
+    balls: Query<&Velocity, Without<CueBall>>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    cursor: Cursor,
+    mut played: EventWriter<ShotPlayed>,
+) {
+    let Ok((transform, mut velocity, mut spin, mut impulse)) = cue_ball.get_single_mut() else {
+        // the cue ball is in a pocket until it is put back in the D
+        shot.ready = false;
+        return;
+    };
+    shot.ready = is_at_rest(&velocity) && balls.iter().all(is_at_rest);
+    if !shot.ready {
+        shot.drag_start = None;
+        shot.power = 0.0;
+        return;
+    }
+
+    for (key, step) in [
+        (KeyCode::W, Vec2::Y),
+        (KeyCode::S, Vec2::NEG_Y),
+        (KeyCode::A, Vec2::NEG_X),
+        (KeyCode::D, Vec2::X),
+    ] {
+        if keys.just_pressed(key) {
+            shot.tip = (shot.tip + step * TIP_STEP).clamp_length_max(1.0);
+        }
+    }
+
+    let cursor = cursor.world_position();
+    let ball = transform.translation.truncate();
+
+    match (shot.drag_start, cursor) {
+        (None, Some(cursor)) => {
+            if let Some(aim) = (cursor - ball).try_normalize() {
+                shot.aim = aim;
+            }
+            if buttons.just_pressed(MouseButton::Left) {
+                shot.drag_start = Some(cursor);
+            }
+        }
+        (Some(start), Some(cursor)) => {
+            shot.power = ((start - cursor).dot(shot.aim) / MAX_DRAG).clamp(0.0, 1.0);
+        }
+        _ => {}
+    }
+
+    if shot.drag_start.is_none() || !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    if shot.power > 0.0 {
+        let speed = shot.power * CONFIG.max_shot_speed;
+        impulse.impulse = shot.aim * speed * CONFIG.ball_mass;
+        // A tip above or below the middle of the ball spins it forwards or
+        // backwards, one to the side spins it around. Striking the ball 2/5 of
+        // its radius above the middle makes it roll right away.
+        let offset = shot.tip * CONFIG.max_tip_offset;
+        let spin_per_offset = 5.0 * speed / (2.0 * CONFIG.ball_radius);
+        spin.0 = Vec2::new(-shot.aim.y, shot.aim.x) * offset.y * spin_per_offset;
+        velocity.angvel = -offset.x * spin_per_offset;
+        shot.tip = Vec2::ZERO;
+        played.send(ShotPlayed);
+    }
+    shot.drag_start = None;
+    shot.power = 0.0;
+}
+
+pub fn update_cue_stick(
+    shot: Res<Shot>,
+    cue_ball: Query<&Transform, With<CueBall>>,
+    mut stick: Query<&mut Transform, (With<CueStick>, Without<CueBall>)>,
+    mut stick_visibility: Query<&mut Visibility, With<CueStick>>,
+    mut meter: Query<&mut Sprite, With<PowerMeter>>,
+) {
+    let (Ok(ball), Ok(mut transform), Ok(mut visibility)) = (
+        cue_ball.get_single(),
+        stick.get_single_mut(),
+        stick_visibility.get_single_mut(),
+    ) else {
+        return;
+    };
+    *visibility = if shot.ready {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    let distance = CONFIG.ball_radius + CUE_GAP + shot.power * MAX_PULL + CUE_LENGTH / 2.0;
+    let center = ball.translation.truncate() - shot.aim * distance;
+    *transform = Transform::from_translation(center.extend(25.0))
+        .with_rotation(Quat::from_rotation_z(shot.aim.y.atan2(shot.aim.x)));
+
+    if let Ok(mut sprite) = meter.get_single_mut() {
+        sprite.custom_size = Some(Vec2::new(METER_SIZE.x * shot.power, METER_SIZE.y));
+    }
+}
+
+// The line the cue ball takes up to the first ball or cushion it touches, the
+// cue ball where it touches it and the way an object ball goes from there. Next
+// to the power meter the spot the tip will hit the cue ball.
+pub fn draw_aim(
+    mut gizmos: Gizmos,
+    shot: Res<Shot>,
+    cue_ball: Query<(Entity, &Transform), With<CueBall>>,
+    balls: Query<&Transform, With<Ball>>,
+    rapier_context: Res<RapierContext>,
+) {
+    let indicator = spin_indicator_position();
+    gizmos.circle_2d(indicator, SPIN_INDICATOR_RADIUS, Color::WHITE);
+    gizmos.circle_2d(
+        indicator + shot.tip * CONFIG.max_tip_offset * SPIN_INDICATOR_RADIUS,
+        1.0,
+        Color::RED,
+    );
+
+    let Ok((entity, transform)) = cue_ball.get_single() else {
+        return;
+    };
+    if !shot.ready {
+        return;
+    }
+    let start = transform.translation.truncate();
+    let Some((hit, toi)) = rapier_context.cast_shape(
+        start,
+        0.0,
+        shot.aim,
+        &Collider::ball(CONFIG.ball_radius),
+        CONFIG.table_size.as_vec2().length(),
+        true,
+        QueryFilter::default()
+            .exclude_collider(entity)
+            .exclude_sensors(),
+    ) else {
+        return;
+    };
+    let contact = start + shot.aim * toi.toi;
+    gizmos.line_2d(start, contact, Color::WHITE);
+    gizmos.circle_2d(contact, CONFIG.ball_radius, Color::WHITE);
+
+    // an object ball goes off along the line through both centers
+    if let Ok(object) = balls.get(hit) {
+        let object = object.translation.truncate();
+        let direction = (object - contact).normalize_or_zero();
+        gizmos.line_2d(object, object + direction * PREVIEW_LENGTH, Color::YELLOW);
+    }
+}
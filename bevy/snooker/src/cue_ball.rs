@@ -2,9 +2,10 @@ use bevy::{
     prelude::*,
     render::{extract_resource::ExtractResource, render_resource::Buffer, renderer::RenderQueue}, sprite::MaterialMesh2dBundle,
 };
-use bevy_rapier3d::prelude::{RigidBody, Collider};
 
-use crate::{camera::MainCamera, config::CONFIG, selection::Selection};
+use bevy_rapier2d::prelude::{ActiveEvents, CollisionEvent, ExternalImpulse, Velocity};
+
+use crate::{ball::{Ball, BallId}, camera::MainCamera, config::CONFIG, physics::{ball_physics, is_at_rest}, pocket::{return_to_table, Potted}, game::Game, rules::CUE, selection::Selection, table::free_spot_in_d};
 
 #[derive(Resource, Default)]
 pub struct CueBallPosition(Vec2);
@@ -20,6 +21,14 @@ impl ExtractResource for CueBallPosition {
 #[derive(Component)]
 pub struct CueBall;
 
+type PottedCueBall = (With<CueBall>, With<Potted>);
+
+// the cue ball touched another ball
+#[derive(Event)]
+pub struct CueBallHit {
+    pub ball: Entity,
+}
+
 #[derive(Resource)]
 pub struct CueBallBuffer(pub Buffer);
 
@@ -55,18 +64,73 @@ pub fn setup_cue_ball(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    game: Res<Game>,
 ) {
+    // in the D, clear of the rack and of the spots
+    let occupied: Vec<Vec2> = game
+        .rack()
+        .into_iter()
+        .map(|(_, position)| position)
+        .chain(game.spots())
+        .collect();
+    let position = free_spot_in_d(&occupied).unwrap_or_default();
     commands
-        .spawn(RigidBody::Fixed)
-        .insert(Collider::ball(CONFIG.ball_radius))
+        .spawn(ball_physics())
+        .insert(ExternalImpulse::default())
+        .insert(ActiveEvents::COLLISION_EVENTS)
         .insert(MaterialMesh2dBundle {
             mesh: meshes
                 .add(shape::Circle::new(CONFIG.ball_radius).into())
                 .into(),
             material: materials.add(ColorMaterial::from(Color::WHITE)),
-            transform: Transform::from_translation(CONFIG.cue_ball_starting_position),
+            transform: Transform::from_translation(position.extend(20.0)),
             ..default()
         })
         .insert(CueBall)
+        .insert(BallId(CUE))
         .insert(Selection { selected: false});
 }
+
+// a cue ball that went into a pocket comes back in the D once every ball stopped
+pub fn respawn_cue_ball(
+    mut commands: Commands,
+    cue_ball: Query<(Entity, &Transform), PottedCueBall>,
+    balls: Query<(&Transform, &Velocity), Without<Potted>>,
+    game: Res<Game>,
+) {
+    let Ok((entity, transform)) = cue_ball.get_single() else {
+        return;
+    };
+    if !balls.iter().all(|(_, velocity)| is_at_rest(velocity)) {
+        return;
+    }
+    // keeping clear of the spots in case a ball is respotted
+    let occupied: Vec<Vec2> = balls
+        .iter()
+        .map(|(ball, _)| ball.translation.truncate())
+        .chain(game.spots())
+        .collect();
+    if let Some(spot) = free_spot_in_d(&occupied) {
+        return_to_table(&mut commands, entity, spot.extend(transform.translation.z));
+    }
+}
+
+pub fn detect_cue_ball_hits(
+    mut collisions: EventReader<CollisionEvent>,
+    cue_ball: Query<Entity, With<CueBall>>,
+    balls: Query<(), With<Ball>>,
+    mut hits: EventWriter<CueBallHit>,
+) {
+    let Ok(cue_ball) = cue_ball.get_single() else {
+        return;
+    };
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = *event else {
+            continue;
+        };
+        let other = if a == cue_ball { b } else { a };
+        if (a == cue_ball || b == cue_ball) && balls.contains(other) {
+            hits.send(CueBallHit { ball: other });
+        }
+    }
+}
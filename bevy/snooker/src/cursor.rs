@@ -1,40 +1,46 @@
-use bevy::{prelude::*, window::PrimaryWindow};
-use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
+use bevy_rapier2d::prelude::{QueryFilter, RapierContext};
 
 use crate::{camera::MainCamera, selection::{select, Selection, de_select}};
 
+#[derive(SystemParam)]
+pub struct Cursor<'w, 's> {
+    q_window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    q_camera: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<MainCamera>>,
+}
+
+impl Cursor<'_, '_> {
+    // the point of the table under the cursor
+    pub fn world_position(&self) -> Option<Vec2> {
+        let cursor = self.q_window.get_single().ok()?.cursor_position()?;
+        let (camera, transform) = self.q_camera.get_single().ok()?;
+        camera.viewport_to_world_2d(transform, cursor)
+    }
+}
+
 pub fn handle_cursor(
     mut commands: Commands,
     selected_q: Query<&Selection>,
     buttons: Res<Input<MouseButton>>,
-    q_window: Query<&Window, With<PrimaryWindow>>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    cursor: Cursor,
     rapier_context: Res<RapierContext>,
 ) {
-    if buttons.just_pressed(MouseButton::Left) {
-        let (camera, camera_transform) = q_camera.single();
-        let window = q_window.single();
+    if buttons.just_pressed(MouseButton::Right) {
+        let Some(point) = cursor.world_position() else {
+            return;
+        };
 
-        if let Some(entity) = window
-            .cursor_position()
-            .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-            .and_then(|ray| {
-                rapier_context.cast_ray(
-                    ray.origin,
-                    ray.direction,
-                    1000.0,
-                    true,
-                    QueryFilter::default(),
-                )
-            })
-        {
-            if let Ok(selection) = selected_q.get(entity.0) {
-                if selection.selected {
-                    de_select(&mut commands, entity.0);
-                } else {
-                    select(&mut commands, entity.0);
-                }
+        // the first selectable collider under the cursor
+        rapier_context.intersections_with_point(point, QueryFilter::default(), |entity| {
+            let Ok(selection) = selected_q.get(entity) else {
+                return true;
+            };
+            if selection.selected {
+                de_select(&mut commands, entity);
+            } else {
+                select(&mut commands, entity);
             }
-        }
+            false
+        });
     }
 }
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+
+use crate::{
+    config::CONFIG,
+    rules::{
+        EightBall, NineBall, RuleSet, SnookerFrame, BLACK, BLUE, BROWN, CUE, GREEN, PINK, RED,
+        YELLOW,
+    },
+    table::{color_spot, foot_spot, free_spot_behind, rack_spot, respot_position},
+};
+
+// the reds of snooker are racked in a triangle of five rows
+const RED_ROWS: u32 = 5;
+
+// Eight-ball with the 8 in the middle and a solid and a stripe in the back
+// corners, nine-ball as a diamond with the 1 in front and the 9 in the middle.
+const EIGHT_BALL_RACK: [&[u8]; 5] = [
+    &[1],
+    &[9, 2],
+    &[10, 8, 3],
+    &[11, 4, 12, 5],
+    &[6, 13, 7, 14, 15],
+];
+const NINE_BALL_RACK: [&[u8]; 5] = [&[1], &[2, 3], &[4, 9, 5], &[6, 7], &[8]];
+
+// The colors of balls 1 to 8 in pool, 9 to 15 are striped in the colors of 1 to 7.
+const POOL_COLORS: [Color; 8] = [
+    Color::rgb(1.0, 0.8, 0.0),
+    Color::rgb(0.0, 0.2, 0.8),
+    Color::rgb(0.9, 0.0, 0.0),
+    Color::rgb(0.4, 0.1, 0.6),
+    Color::rgb(1.0, 0.5, 0.0),
+    Color::rgb(0.0, 0.5, 0.2),
+    Color::rgb(0.5, 0.1, 0.1),
+    Color::BLACK,
+];
+
+// What is played on the table, chosen when starting it with
+// `cargo run -p snooker -- eight-ball` or `nine-ball`, snooker otherwise.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Game {
+    Snooker,
+    EightBall,
+    NineBall,
+}
+
+impl Game {
+    pub fn from_args() -> Self {
+        match std::env::args().nth(1).as_deref() {
+            Some("eight-ball") => Game::EightBall,
+            Some("nine-ball") => Game::NineBall,
+            _ => Game::Snooker,
+        }
+    }
+
+    pub fn rules(self) -> Box<dyn RuleSet> {
+        match self {
+            Game::Snooker => Box::new(SnookerFrame::new(RED_ROWS * (RED_ROWS + 1) / 2)),
+            Game::EightBall => Box::new(EightBall::default()),
+            Game::NineBall => Box::new(NineBall::default()),
+        }
+    }
+
+    // The object balls and where they start. The reds of snooker are racked
+    // behind the pink, the pool balls on the foot spot.
+    pub fn rack(self) -> Vec<(u8, Vec2)> {
+        let (rows, front): (Vec<Vec<u8>>, Vec2) = match self {
+            Game::Snooker => {
+                let reds = (1..=RED_ROWS).map(|row| vec![RED; row as usize]).collect();
+                let front = foot_spot() + Vec2::X * (2.0 * CONFIG.ball_radius + 1.0);
+                (reds, front)
+            }
+            Game::EightBall => (
+                EIGHT_BALL_RACK.iter().map(|row| row.to_vec()).collect(),
+                foot_spot(),
+            ),
+            Game::NineBall => (
+                NINE_BALL_RACK.iter().map(|row| row.to_vec()).collect(),
+                foot_spot(),
+            ),
+        };
+        let mut rack: Vec<(u8, Vec2)> = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row, balls)| {
+                balls
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, &ball)| (ball, rack_spot(front, row, i, balls.len())))
+            })
+            .collect();
+        if self == Game::Snooker {
+            rack.extend((YELLOW..=BLACK).filter_map(|color| Some((color, color_spot(color)?))));
+        }
+        rack
+    }
+
+    pub fn ball_color(self, ball: u8) -> Color {
+        if ball == CUE {
+            return Color::WHITE;
+        }
+        match self {
+            Game::Snooker => match ball {
+                RED => Color::RED,
+                YELLOW => Color::YELLOW,
+                GREEN => Color::DARK_GREEN,
+                BROWN => Color::rgb(0.5, 0.25, 0.1),
+                BLUE => Color::BLUE,
+                PINK => Color::PINK,
+                _ => Color::BLACK,
+            },
+            Game::EightBall | Game::NineBall => POOL_COLORS[(ball as usize - 1) % 8],
+        }
+    }
+
+    pub fn is_striped(self, ball: u8) -> bool {
+        self != Game::Snooker && ball > 8
+    }
+
+    // the spots balls are put back on, which the cue ball keeps clear of
+    pub fn spots(self) -> Vec<Vec2> {
+        match self {
+            Game::Snooker => (YELLOW..=BLACK).filter_map(color_spot).collect(),
+            Game::EightBall | Game::NineBall => vec![foot_spot()],
+        }
+    }
+
+    // in pool a ball goes back on the foot spot, or behind it if that is taken
+    pub fn respot_position(self, ball: u8, occupied: &[Vec2]) -> Option<Vec2> {
+        match self {
+            Game::Snooker => respot_position(ball, occupied),
+            Game::EightBall | Game::NineBall => free_spot_behind(foot_spot(), occupied),
+        }
+    }
+}
@@ -1,18 +1,22 @@
 use ball::{track_ball_positions, BallPositions, setup_balls};
 use bevy::{prelude::*, window::*};
-use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
+use bevy_rapier2d::prelude::{NoUserData, RapierPhysicsPlugin};
 
 use camera::MainCamera;
 use config::CONFIG;
-use cue_ball::{track_cue_ball_position, CueBallPosition, setup_cue_ball};
+use cue::{draw_aim, play_cue, setup_cue, update_cue_stick, Shot, ShotPlayed};
+use cue_ball::{detect_cue_ball_hits, respawn_cue_ball, track_cue_ball_position, CueBallHit, CueBallPosition, setup_cue_ball};
 use cursor::handle_cursor;
 use debug::draw_viewport_rect;
+use game::Game;
 use image::setup_image;
-use movement::move_cue_ball;
+use physics::{apply_cloth_friction, disable_gravity};
 use plugin::GpuComputePlugin;
-use pocket::{setup_pockets, PocketPositions, track_pocket_selection};
+use pocket::{detect_pots, log_pots, setup_pockets, BallPotted, PocketPositions, track_pocket_selection};
+use referee::{referee, Referee};
 use selection::highlight_selected;
-use wall::setup_walls;
+use table::draw_table_markings;
+use wall::{setup_cushions, setup_walls};
 
 mod selection;
 mod buffer_size;
@@ -20,15 +24,20 @@ mod ball;
 mod bind_group;
 mod camera;
 mod config;
+mod cue;
 mod cue_ball;
 mod cursor;
 mod debug;
+mod game;
 mod image;
-mod movement;
 mod node;
+mod physics;
 mod pipeline;
 mod plugin;
 mod pocket;
+mod referee;
+mod rules;
+mod table;
 mod time;
 mod wall;
 
@@ -47,19 +56,28 @@ fn main() {
                 ..default()
             }),
             GpuComputePlugin,
-            RapierPhysicsPlugin::<NoUserData>::default(),
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(CONFIG.pixels_per_meter),
         ))
-        .add_systems(Startup, (setup, setup_image, setup_cue_ball, setup_balls, setup_walls, setup_pockets))
+        .insert_resource(Game::from_args())
+        .add_event::<BallPotted>()
+        .add_event::<ShotPlayed>()
+        .add_event::<CueBallHit>()
+        .add_systems(Startup, (setup, setup_image, setup_cue_ball, setup_balls, setup_walls, setup_cushions, setup_pockets, setup_cue, disable_gravity))
         .add_systems(
             Update,
             (
-                move_cue_ball,
+                apply_cloth_friction,
+                (detect_pots, log_pots, respawn_cue_ball).chain(),
+                (play_cue, update_cue_stick, draw_aim).chain(),
+                detect_cue_ball_hits,
+                referee.after(play_cue).after(respawn_cue_ball).after(detect_cue_ball_hits),
                 handle_cursor,
                 track_cue_ball_position,
                 highlight_selected,
                 track_ball_positions,
                 track_pocket_selection,
                 draw_viewport_rect,
+                draw_table_markings,
             ),
         )
         .run();
@@ -67,9 +85,12 @@ fn main() {
 
 fn setup(
     mut commands: Commands,
+    game: Res<Game>,
 ) {
     commands.spawn((Camera2dBundle::default(), MainCamera));
     commands.insert_resource(CueBallPosition::default());
     commands.insert_resource(BallPositions::default());
     commands.insert_resource(PocketPositions::default());
+    commands.insert_resource(Shot::default());
+    commands.insert_resource(Referee::new(*game));
 }
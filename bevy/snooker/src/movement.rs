@@ -1,24 +0,0 @@
-use bevy::prelude::*;
-
-use crate::cue_ball::CueBall;
-
-pub fn move_cue_ball(
-    mut cue_ball: Query<(&mut Transform, With<CueBall>)>,
-    input: Res<Input<KeyCode>>,
-    time: Res<Time>,
-) {
-    for (mut transform, _) in &mut cue_ball {
-        if input.pressed(KeyCode::W) {
-            transform.translation.y += 100.0 * time.delta_seconds();
-        }
-        if input.pressed(KeyCode::S) {
-            transform.translation.y -= 100.0 * time.delta_seconds();
-        }
-        if input.pressed(KeyCode::D) {
-            transform.translation.x += 100.0 * time.delta_seconds();
-        }
-        if input.pressed(KeyCode::A) {
-            transform.translation.x -= 100.0 * time.delta_seconds();
-        }
-    }
-}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::config::CONFIG;
+
+const GRAVITY: f32 = 9.81;
+// slower than this the point of a ball touching the cloth counts as resting
+const SLIP_EPSILON: f32 = 1.0;
+// and slower than this a rolling ball stops
+const REST_EPSILON: f32 = 2.0;
+
+// Top and back spin, the rotation of a ball about the x and y axes of the
+// table in radians per second. Rapier only knows the rotation about the
+// vertical axis, which is the side spin of the ball.
+#[derive(Component, Default)]
+pub struct Spin(pub Vec2);
+
+// what every ball is made of, the walls use the same combine rules so the
+// cushions get their own restitution and friction
+pub fn ball_physics() -> impl Bundle {
+    (
+        RigidBody::Dynamic,
+        Collider::ball(CONFIG.ball_radius),
+        // impulses are in pixels then, see play_cue
+        ColliderMassProperties::Mass(CONFIG.ball_mass),
+        Restitution {
+            coefficient: CONFIG.ball_restitution,
+            combine_rule: CoefficientCombineRule::Min,
+        },
+        Friction {
+            coefficient: CONFIG.ball_friction,
+            combine_rule: CoefficientCombineRule::Max,
+        },
+        Velocity::zero(),
+        Spin::default(),
+        Ccd::enabled(),
+    )
+}
+
+pub fn cushion_physics(half_size: Vec2) -> impl Bundle {
+    (
+        RigidBody::Fixed,
+        Collider::cuboid(half_size.x, half_size.y),
+        Restitution {
+            coefficient: CONFIG.cushion_restitution,
+            combine_rule: CoefficientCombineRule::Min,
+        },
+        Friction {
+            coefficient: CONFIG.cushion_friction,
+            combine_rule: CoefficientCombineRule::Max,
+        },
+    )
+}
+
+pub fn is_at_rest(velocity: &Velocity) -> bool {
+    velocity.linvel.length() < REST_EPSILON
+}
+
+// the table is seen from above
+pub fn disable_gravity(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.gravity = Vec2::ZERO;
+}
+
+// how fast the point of the ball touching the cloth moves over it
+fn slip(linvel: Vec2, spin: Vec2) -> Vec2 {
+    Vec2::new(
+        linvel.x - CONFIG.ball_radius * spin.y,
+        linvel.y + CONFIG.ball_radius * spin.x,
+    )
+}
+
+// A ball slides as long as the point touching the cloth moves. The cloth pulls
+// that point back, slowing the ball down and spinning it up until it rolls,
+// which for a solid ball takes 2/7 of the slip off its speed. A rolling ball
+// only slows down a little, and side spin wears off on its own.
+pub fn apply_cloth_friction(mut balls: Query<(&mut Velocity, &mut Spin)>, time: Res<Time>) {
+    let dt = time.delta_seconds();
+    let gravity = GRAVITY * CONFIG.pixels_per_meter;
+    // the change of spin for a change of speed at the bottom of the ball
+    let spin_per_speed = 5.0 / (2.0 * CONFIG.ball_radius);
+
+    for (mut velocity, mut spin) in &mut balls {
+        let slip = slip(velocity.linvel, spin.0);
+        if slip.length() > SLIP_EPSILON {
+            let direction = slip.normalize();
+            let change = (CONFIG.sliding_friction * gravity * dt).min(slip.length() * 2.0 / 7.0);
+            velocity.linvel -= direction * change;
+            spin.0 += Vec2::new(-direction.y, direction.x) * change * spin_per_speed;
+        } else {
+            let speed = velocity.linvel.length();
+            let slowed = speed - CONFIG.rolling_friction * gravity * dt;
+            velocity.linvel = if slowed < REST_EPSILON {
+                Vec2::ZERO
+            } else {
+                velocity.linvel * slowed / speed
+            };
+            spin.0 = Vec2::new(-velocity.linvel.y, velocity.linvel.x) / CONFIG.ball_radius;
+        }
+
+        let side = CONFIG.spinning_friction * gravity * spin_per_speed * dt;
+        velocity.angvel = velocity.angvel.signum() * (velocity.angvel.abs() - side).max(0.0);
+    }
+}
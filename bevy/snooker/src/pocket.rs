@@ -1,17 +1,20 @@
 use bevy::{prelude::*, sprite::MaterialMesh2dBundle, render::{extract_resource::ExtractResource, render_resource::Buffer, renderer::RenderQueue}};
 
-use bevy_rapier3d::prelude::{RigidBody, Collider};
+use bevy_rapier2d::prelude::{Collider, ColliderDisabled, RapierContext, RigidBody, RigidBodyDisabled, Sensor, Velocity};
 use bytemuck::{Zeroable, Pod};
 
-use crate::{config::CONFIG, selection::Selection, camera::MainCamera};
+use crate::{config::CONFIG, selection::Selection, camera::MainCamera, physics::Spin};
 
 #[repr(C)]
-#[derive(Pod, Copy, Clone, Default)]
+#[derive(Copy, Clone, Default)]
 pub struct PocketStatus {
     position: [f32; 3],
     selected: i32
 }
 
+// a vec3 and an i32, 16 bytes without padding
+unsafe impl Pod for PocketStatus {}
+
 unsafe impl Zeroable for PocketStatus {
     fn zeroed() -> Self {
         PocketStatus {
@@ -35,6 +38,16 @@ impl ExtractResource for PocketPositions {
 #[derive(Component)]
 pub struct Pocket;
 
+// a ball that went into a pocket, kept off the table until it is put back
+#[derive(Component)]
+pub struct Potted;
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BallPotted {
+    pub ball: Entity,
+    pub pocket: Entity,
+}
+
 #[derive(Resource)]
 pub struct PocketBuffer(pub Buffer);
 
@@ -80,6 +93,7 @@ pub fn setup_pockets(
         commands
             .spawn(RigidBody::Fixed)
             .insert(Collider::ball(CONFIG.pocket_radius))
+            .insert(Sensor)
             .insert(
             MaterialMesh2dBundle {
                 mesh: meshes
@@ -100,6 +114,7 @@ pub fn setup_pockets(
         commands
             .spawn(RigidBody::Fixed)
             .insert(Collider::ball(CONFIG.pocket_radius))
+            .insert(Sensor)
             .insert(
             MaterialMesh2dBundle {
                 mesh: meshes
@@ -119,3 +134,55 @@ pub fn setup_pockets(
     }
 
 }
+
+// A ball is potted as soon as its center is over a pocket. The sensor of the
+// pocket finds the balls touching it, the ones whose center is also inside are
+// potted. They stay where they are, but hidden and out of the physics.
+pub fn detect_pots(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut balls: Query<(&Transform, &mut Velocity, &mut Spin), Without<Potted>>,
+    pockets: Query<(Entity, &Transform), With<Pocket>>,
+    mut potted: EventWriter<BallPotted>,
+) {
+    for (pocket, pocket_transform) in &pockets {
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(pocket) {
+            let ball = if collider1 == pocket { collider2 } else { collider1 };
+            let Ok((transform, mut velocity, mut spin)) = balls.get_mut(ball) else {
+                continue;
+            };
+            let distance = transform
+                .translation
+                .truncate()
+                .distance(pocket_transform.translation.truncate());
+            if !intersecting || distance >= CONFIG.pocket_radius {
+                continue;
+            }
+            *velocity = Velocity::zero();
+            spin.0 = Vec2::ZERO;
+            commands.entity(ball).insert((
+                Potted,
+                RigidBodyDisabled,
+                ColliderDisabled,
+                Visibility::Hidden,
+            ));
+            potted.send(BallPotted { ball, pocket });
+        }
+    }
+}
+
+pub fn return_to_table(commands: &mut Commands, ball: Entity, position: Vec3) {
+    commands
+        .entity(ball)
+        .remove::<(Potted, RigidBodyDisabled, ColliderDisabled)>()
+        .insert((
+            Visibility::Inherited,
+            Transform::from_translation(position),
+        ));
+}
+
+pub fn log_pots(mut potted: EventReader<BallPotted>) {
+    for event in potted.read() {
+        info!("ball {:?} went into pocket {:?}", event.ball, event.pocket);
+    }
+}
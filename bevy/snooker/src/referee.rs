@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+use crate::{
+    ball::BallId,
+    cue::{Shot, ShotPlayed},
+    cue_ball::CueBallHit,
+    game::Game,
+    pocket::{return_to_table, BallPotted, Potted},
+    rules::RuleSet,
+};
+
+// Keeps the score of the frame being played. It hears about a shot from the cue
+// and judges it once the cue can be played again.
+#[derive(Resource)]
+pub struct Referee {
+    game: Game,
+    frame: Box<dyn RuleSet>,
+    shot_in_play: bool,
+}
+
+impl Referee {
+    pub fn new(game: Game) -> Self {
+        Referee {
+            game,
+            frame: game.rules(),
+            shot_in_play: false,
+        }
+    }
+}
+
+pub fn referee(
+    mut commands: Commands,
+    mut referee: ResMut<Referee>,
+    shot: Res<Shot>,
+    mut played: EventReader<ShotPlayed>,
+    mut hits: EventReader<CueBallHit>,
+    mut potted: EventReader<BallPotted>,
+    balls: Query<(Entity, &BallId, &Transform, Option<&Potted>)>,
+) {
+    let id = |entity| balls.get(entity).ok().map(|(_, id, _, _)| id.0);
+    for hit in hits.read() {
+        if let Some(ball) = id(hit.ball) {
+            referee.frame.contact(ball);
+        }
+    }
+    for pot in potted.read() {
+        if let Some(ball) = id(pot.ball) {
+            referee.frame.pot(ball);
+        }
+    }
+
+    if referee.shot_in_play && shot.ready {
+        referee.shot_in_play = false;
+        let outcome = referee.frame.end_shot();
+
+        let mut occupied: Vec<Vec2> = balls
+            .iter()
+            .filter(|(_, _, _, potted)| potted.is_none())
+            .map(|(_, _, transform, _)| transform.translation.truncate())
+            .collect();
+        for &color in &outcome.respot {
+            let ball = balls
+                .iter()
+                .find(|(_, id, _, potted)| id.0 == color && potted.is_some());
+            let (Some((entity, _, transform, _)), Some(spot)) =
+                (ball, referee.game.respot_position(color, &occupied))
+            else {
+                continue;
+            };
+            return_to_table(&mut commands, entity, spot.extend(transform.translation.z));
+            occupied.push(spot);
+        }
+
+        match outcome.foul {
+            Some((foul, points)) => info!(
+                "player {}: foul {:?}, {} points to the opponent",
+                outcome.player + 1,
+                foul,
+                points
+            ),
+            None => info!("player {}: {} points", outcome.player + 1, outcome.points),
+        }
+        let [first, second] = referee.frame.scores();
+        info!("score {} - {}", first, second);
+        if let (true, Some(winner)) = (outcome.frame_over, referee.frame.winner()) {
+            info!("frame over, player {} wins", winner + 1);
+        } else {
+            info!(
+                "player {} to play, on {}",
+                referee.frame.player() + 1,
+                referee.frame.ball_on()
+            );
+        }
+    }
+
+    if played.read().count() > 0 {
+        referee.shot_in_play = true;
+    }
+}
@@ -0,0 +1,238 @@
+// A rack of eight-ball. Balls 1 to 7 are the solids, 9 to 15 the stripes. The
+// table is open until a player pots a ball, from then on that player is on
+// its group and has to clear it before potting the 8.
+
+use super::{Foul, RuleSet, ShotOutcome, CUE};
+
+const EIGHT: u8 = 8;
+const GROUP_SIZE: u32 = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Group {
+    Solids,
+    Stripes,
+}
+
+fn group_of(ball: u8) -> Option<Group> {
+    match ball {
+        1..=7 => Some(Group::Solids),
+        9..=15 => Some(Group::Stripes),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EightBall {
+    player: usize,
+    // of each player, once the table is no longer open
+    groups: Option<[Group; 2]>,
+    // of the solids and of the stripes
+    potted_of_group: [u32; 2],
+    winner: Option<usize>,
+    // of the shot being played
+    first_contact: Option<u8>,
+    potted: Vec<u8>,
+}
+
+impl EightBall {
+    fn group(&self, player: usize) -> Option<Group> {
+        self.groups.map(|groups| groups[player])
+    }
+
+    fn cleared(&self, group: Group) -> bool {
+        self.potted_of_group[group as usize] == GROUP_SIZE
+    }
+
+    // whether the player may hit this ball first
+    fn is_on(&self, ball: u8) -> bool {
+        match self.group(self.player) {
+            None => ball != EIGHT,
+            Some(group) if self.cleared(group) => ball == EIGHT,
+            Some(group) => group_of(ball) == Some(group),
+        }
+    }
+
+    fn foul(&self, first: Option<u8>, potted: &[u8]) -> Option<Foul> {
+        if potted.contains(&CUE) {
+            return Some(Foul::CueBallPotted);
+        }
+        match first {
+            None => Some(Foul::NoBallHit),
+            Some(first) if !self.is_on(first) => Some(Foul::WrongBallFirst(first)),
+            _ => None,
+        }
+    }
+}
+
+impl RuleSet for EightBall {
+    fn contact(&mut self, ball: u8) {
+        self.first_contact.get_or_insert(ball);
+    }
+
+    fn pot(&mut self, ball: u8) {
+        self.potted.push(ball);
+    }
+
+    // Nothing is respotted. Potting the 8 ends the rack, won if the player
+    // was on it and lost otherwise.
+    fn end_shot(&mut self) -> ShotOutcome {
+        let first = self.first_contact.take();
+        let potted = std::mem::take(&mut self.potted);
+        let mut outcome = ShotOutcome {
+            player: self.player,
+            points: 0,
+            foul: None,
+            respot: Vec::new(),
+            frame_over: false,
+        };
+        if self.winner.is_some() {
+            outcome.frame_over = true;
+            return outcome;
+        }
+        let foul = self.foul(first, &potted);
+        let on_eight = self.is_on(EIGHT);
+        for group in potted.iter().filter_map(|&ball| group_of(ball)) {
+            self.potted_of_group[group as usize] += 1;
+        }
+
+        if potted.contains(&EIGHT) {
+            self.winner = Some(if foul.is_none() && on_eight {
+                self.player
+            } else {
+                1 - self.player
+            });
+            outcome.foul = foul.map(|foul| (foul, 0));
+            outcome.frame_over = true;
+            return outcome;
+        }
+        if let Some(foul) = foul {
+            outcome.foul = Some((foul, 0));
+            self.player = 1 - self.player;
+            return outcome;
+        }
+
+        if self.groups.is_none() {
+            if let Some(group) = potted.iter().find_map(|&ball| group_of(ball)) {
+                let other = match group {
+                    Group::Solids => Group::Stripes,
+                    Group::Stripes => Group::Solids,
+                };
+                let mut groups = [other; 2];
+                groups[self.player] = group;
+                self.groups = Some(groups);
+            }
+        }
+        let own = self.group(self.player);
+        outcome.points = potted
+            .iter()
+            .filter(|&&ball| group_of(ball).is_some() && group_of(ball) == own)
+            .count() as u32;
+        if outcome.points == 0 {
+            self.player = 1 - self.player;
+        }
+        outcome
+    }
+
+    // how many balls of their group each player has potted
+    fn scores(&self) -> [u32; 2] {
+        let mut scores = [0, 0];
+        if let Some(groups) = self.groups {
+            for (score, group) in scores.iter_mut().zip(groups) {
+                *score = self.potted_of_group[group as usize];
+            }
+        }
+        scores
+    }
+
+    fn player(&self) -> usize {
+        self.player
+    }
+
+    fn ball_on(&self) -> String {
+        match self.group(self.player) {
+            None => "any ball but the 8".to_string(),
+            Some(group) if self.cleared(group) => "the 8".to_string(),
+            Some(Group::Solids) => "a solid".to_string(),
+            Some(Group::Stripes) => "a stripe".to_string(),
+        }
+    }
+
+    fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::shot;
+
+    #[test]
+    fn the_table_is_open_until_a_ball_is_potted() {
+        let mut rack = EightBall::default();
+        let outcome = shot(&mut rack, Some(12), &[]);
+        assert_eq!(outcome.foul, None);
+        assert_eq!(rack.groups, None);
+        assert_eq!(rack.player(), 1);
+        assert_eq!(rack.ball_on(), "any ball but the 8");
+
+        // the group of the first ball potted, whatever was hit first
+        let outcome = shot(&mut rack, Some(12), &[3]);
+        assert_eq!(outcome.points, 1);
+        assert_eq!(rack.groups, Some([Group::Stripes, Group::Solids]));
+        assert_eq!(rack.player(), 1);
+        assert_eq!(rack.ball_on(), "a solid");
+        assert_eq!(rack.scores(), [0, 1]);
+    }
+
+    #[test]
+    fn hitting_the_8_first_on_an_open_table_is_a_foul() {
+        let mut rack = EightBall::default();
+        let outcome = shot(&mut rack, Some(EIGHT), &[2]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(EIGHT), 0)));
+        assert_eq!(rack.groups, None);
+        assert_eq!(rack.player(), 1);
+    }
+
+    #[test]
+    fn hitting_the_other_group_first_is_a_foul() {
+        let mut rack = EightBall::default();
+        shot(&mut rack, Some(1), &[1]);
+        let outcome = shot(&mut rack, Some(9), &[2]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(9), 0)));
+        assert_eq!(outcome.points, 0);
+        assert_eq!(rack.player(), 1);
+        // the solid stays down
+        assert_eq!(rack.scores(), [2, 0]);
+    }
+
+    #[test]
+    fn potting_the_8_before_the_group_is_cleared_loses() {
+        let mut rack = EightBall::default();
+        shot(&mut rack, Some(1), &[1]);
+        let outcome = shot(&mut rack, Some(2), &[EIGHT]);
+        assert!(outcome.frame_over);
+        assert_eq!(rack.winner(), Some(1));
+    }
+
+    #[test]
+    fn potting_the_8_on_a_foul_loses() {
+        let mut rack = EightBall::default();
+        shot(&mut rack, Some(1), &[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(rack.ball_on(), "the 8");
+        let outcome = shot(&mut rack, Some(EIGHT), &[EIGHT, CUE]);
+        assert_eq!(outcome.foul, Some((Foul::CueBallPotted, 0)));
+        assert!(outcome.frame_over);
+        assert_eq!(rack.winner(), Some(1));
+    }
+
+    #[test]
+    fn potting_the_8_after_clearing_the_group_wins() {
+        let mut rack = EightBall::default();
+        shot(&mut rack, Some(1), &[1, 2, 3, 4, 5, 6, 7]);
+        let outcome = shot(&mut rack, Some(EIGHT), &[EIGHT]);
+        assert_eq!(outcome.foul, None);
+        assert!(outcome.frame_over);
+        assert_eq!(rack.winner(), Some(0));
+    }
+}
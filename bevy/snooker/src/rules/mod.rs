@@ -0,0 +1,68 @@
+// The rules of the games played on the table. They know nothing about the
+// table, the physics tell them what the cue ball hit first and what went into
+// the pockets, and they answer once every ball has stopped.
+//
+// Every game numbers its own balls, the cue ball is always 0.
+
+mod eight_ball;
+mod nine_ball;
+mod snooker;
+
+pub use eight_ball::EightBall;
+pub use nine_ball::NineBall;
+pub use snooker::{SnookerFrame, BLACK, BLUE, BROWN, GREEN, PINK, RED, YELLOW};
+
+pub const CUE: u8 = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Foul {
+    NoBallHit,
+    WrongBallFirst(u8),
+    CueBallPotted,
+    WrongBallPotted(u8),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShotOutcome {
+    // who played the shot
+    pub player: usize,
+    pub points: u32,
+    // and how many points it gave the opponent
+    pub foul: Option<(Foul, u32)>,
+    // balls that go back on the table
+    pub respot: Vec<u8>,
+    pub frame_over: bool,
+}
+
+// A frame between two players, fed with what happened during a shot and asked
+// to judge it once every ball has stopped.
+pub trait RuleSet: Send + Sync {
+    // the cue ball touched a ball, only the first one of a shot counts
+    fn contact(&mut self, ball: u8);
+
+    fn pot(&mut self, ball: u8);
+
+    fn end_shot(&mut self) -> ShotOutcome;
+
+    fn scores(&self) -> [u32; 2];
+
+    // whose turn it is
+    fn player(&self) -> usize;
+
+    // what that player has to hit, to tell them
+    fn ball_on(&self) -> String;
+
+    fn winner(&self) -> Option<usize>;
+}
+
+// the cue ball touching `first` before anything else and `potted` going down
+#[cfg(test)]
+fn shot(rules: &mut impl RuleSet, first: Option<u8>, potted: &[u8]) -> ShotOutcome {
+    if let Some(ball) = first {
+        rules.contact(ball);
+    }
+    for &ball in potted {
+        rules.pot(ball);
+    }
+    rules.end_shot()
+}
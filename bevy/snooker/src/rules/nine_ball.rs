@@ -0,0 +1,162 @@
+// A rack of nine-ball with the balls 1 to 9. The lowest ball on the table has
+// to be hit first, after that any ball potted counts and the 9 wins the rack.
+
+use super::{Foul, RuleSet, ShotOutcome, CUE};
+
+const NINE: u8 = 9;
+
+#[derive(Clone, Debug)]
+pub struct NineBall {
+    player: usize,
+    // how many balls each player has potted
+    scores: [u32; 2],
+    // the object balls still on the table, bit n for ball n
+    on_table: u16,
+    winner: Option<usize>,
+    // of the shot being played
+    first_contact: Option<u8>,
+    potted: Vec<u8>,
+}
+
+impl Default for NineBall {
+    fn default() -> Self {
+        NineBall {
+            player: 0,
+            scores: [0, 0],
+            on_table: (1..=NINE).fold(0, |balls, ball| balls | 1 << ball),
+            winner: None,
+            first_contact: None,
+            potted: Vec::new(),
+        }
+    }
+}
+
+impl NineBall {
+    fn lowest(&self) -> u8 {
+        self.on_table.trailing_zeros() as u8
+    }
+
+    fn foul(&self, first: Option<u8>, potted: &[u8]) -> Option<Foul> {
+        if potted.contains(&CUE) {
+            return Some(Foul::CueBallPotted);
+        }
+        match first {
+            None => Some(Foul::NoBallHit),
+            Some(first) if first != self.lowest() => Some(Foul::WrongBallFirst(first)),
+            _ => None,
+        }
+    }
+}
+
+impl RuleSet for NineBall {
+    fn contact(&mut self, ball: u8) {
+        self.first_contact.get_or_insert(ball);
+    }
+
+    fn pot(&mut self, ball: u8) {
+        self.potted.push(ball);
+    }
+
+    // Only a 9 potted on a foul is respotted.
+    fn end_shot(&mut self) -> ShotOutcome {
+        let first = self.first_contact.take();
+        let potted = std::mem::take(&mut self.potted);
+        let mut outcome = ShotOutcome {
+            player: self.player,
+            points: 0,
+            foul: None,
+            respot: Vec::new(),
+            frame_over: false,
+        };
+        if self.winner.is_some() {
+            outcome.frame_over = true;
+            return outcome;
+        }
+        let foul = self.foul(first, &potted);
+        for &ball in potted.iter().filter(|&&ball| ball != CUE) {
+            self.on_table &= !(1 << ball);
+        }
+
+        if let Some(foul) = foul {
+            outcome.foul = Some((foul, 0));
+            if potted.contains(&NINE) {
+                self.on_table |= 1 << NINE;
+                outcome.respot.push(NINE);
+            }
+            self.player = 1 - self.player;
+            return outcome;
+        }
+        outcome.points = potted.len() as u32;
+        self.scores[self.player] += outcome.points;
+        if potted.contains(&NINE) {
+            self.winner = Some(self.player);
+            outcome.frame_over = true;
+        } else if potted.is_empty() {
+            self.player = 1 - self.player;
+        }
+        outcome
+    }
+
+    fn scores(&self) -> [u32; 2] {
+        self.scores
+    }
+
+    fn player(&self) -> usize {
+        self.player
+    }
+
+    fn ball_on(&self) -> String {
+        format!("the {}", self.lowest())
+    }
+
+    fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::shot;
+
+    #[test]
+    fn the_lowest_ball_has_to_be_hit_first() {
+        let mut rack = NineBall::default();
+        let outcome = shot(&mut rack, Some(3), &[3]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(3), 0)));
+        assert_eq!(outcome.points, 0);
+        assert_eq!(rack.player(), 1);
+        assert_eq!(rack.ball_on(), "the 1");
+
+        // the 3 stayed down
+        shot(&mut rack, Some(1), &[1, 2]);
+        assert_eq!(rack.ball_on(), "the 4");
+        assert_eq!(rack.scores(), [0, 2]);
+    }
+
+    #[test]
+    fn the_9_is_respotted_after_a_foul() {
+        let mut rack = NineBall::default();
+        let outcome = shot(&mut rack, Some(2), &[NINE]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(2), 0)));
+        assert_eq!(outcome.respot, vec![NINE]);
+        assert!(!outcome.frame_over);
+        assert_eq!(rack.winner(), None);
+
+        let outcome = shot(&mut rack, Some(1), &[NINE, CUE]);
+        assert_eq!(outcome.foul, Some((Foul::CueBallPotted, 0)));
+        assert_eq!(outcome.respot, vec![NINE]);
+        assert_eq!(rack.winner(), None);
+    }
+
+    #[test]
+    fn a_legal_pot_of_the_9_wins() {
+        let mut rack = NineBall::default();
+        shot(&mut rack, Some(1), &[]);
+        let outcome = shot(&mut rack, Some(1), &[NINE]);
+        assert_eq!(outcome.foul, None);
+        assert_eq!(outcome.points, 1);
+        assert!(outcome.frame_over);
+        assert_eq!(rack.winner(), Some(1));
+    }
+}
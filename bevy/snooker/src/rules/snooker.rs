@@ -0,0 +1,348 @@
+// A frame of snooker between two players. Balls are numbered by their value.
+
+use super::{Foul, RuleSet, ShotOutcome, CUE};
+
+pub const RED: u8 = 1;
+pub const YELLOW: u8 = 2;
+pub const GREEN: u8 = 3;
+pub const BROWN: u8 = 4;
+pub const BLUE: u8 = 5;
+pub const PINK: u8 = 6;
+pub const BLACK: u8 = 7;
+
+// a foul is worth at least this much to the opponent
+const MIN_FOUL_POINTS: u32 = 4;
+
+// what the player has to hit next
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BallOn {
+    Red,
+    // any color after a red, the one hit first counts as nominated
+    Color,
+    // the colors in order once the reds are gone
+    Clearance(u8),
+}
+
+#[derive(Clone, Debug)]
+pub struct SnookerFrame {
+    scores: [u32; 2],
+    player: usize,
+    reds: u32,
+    on: BallOn,
+    over: bool,
+    // of the shot being played
+    first_contact: Option<u8>,
+    potted: Vec<u8>,
+}
+
+impl SnookerFrame {
+    pub fn new(reds: u32) -> Self {
+        SnookerFrame {
+            scores: [0, 0],
+            player: 0,
+            reds,
+            on: if reds > 0 {
+                BallOn::Red
+            } else {
+                BallOn::Clearance(YELLOW)
+            },
+            over: false,
+            first_contact: None,
+            potted: Vec::new(),
+        }
+    }
+
+    fn foul(&self, first: Option<u8>, potted: &[u8]) -> Option<Foul> {
+        if potted.contains(&CUE) {
+            return Some(Foul::CueBallPotted);
+        }
+        let Some(first) = first else {
+            return Some(Foul::NoBallHit);
+        };
+        let (legal_first, allowed) = match self.on {
+            BallOn::Red => (first == RED, RED),
+            BallOn::Color => (first >= YELLOW, first),
+            BallOn::Clearance(color) => (first == color, color),
+        };
+        if !legal_first {
+            return Some(Foul::WrongBallFirst(first));
+        }
+        potted
+            .iter()
+            .find(|&&ball| ball != allowed)
+            .map(|&ball| Foul::WrongBallPotted(ball))
+    }
+
+    // the value of the ball on, of the ball hit first or of any ball potted,
+    // whichever is highest
+    fn foul_points(&self, first: Option<u8>, potted: &[u8]) -> u32 {
+        let on = match self.on {
+            BallOn::Red => RED,
+            BallOn::Color => first.filter(|&ball| ball >= YELLOW).unwrap_or(0),
+            BallOn::Clearance(color) => color,
+        };
+        potted
+            .iter()
+            .chain(first.iter())
+            .fold(on, |points, &ball| points.max(ball)) as u32
+    }
+
+    // the ball on for the next player
+    fn next_turn(&mut self) {
+        self.player = 1 - self.player;
+        if self.on == BallOn::Color {
+            self.on = BallOn::Red;
+        }
+        if self.on == BallOn::Red && self.reds == 0 {
+            self.on = BallOn::Clearance(YELLOW);
+        }
+    }
+}
+
+impl RuleSet for SnookerFrame {
+    fn contact(&mut self, ball: u8) {
+        self.first_contact.get_or_insert(ball);
+    }
+
+    fn pot(&mut self, ball: u8) {
+        self.potted.push(ball);
+    }
+
+    // Reds stay down, whatever else went in is respotted unless it was potted
+    // in order after the last red.
+    fn end_shot(&mut self) -> ShotOutcome {
+        let first = self.first_contact.take();
+        let potted = std::mem::take(&mut self.potted);
+        let mut outcome = ShotOutcome {
+            player: self.player,
+            points: 0,
+            foul: None,
+            respot: Vec::new(),
+            frame_over: false,
+        };
+        if self.over {
+            outcome.frame_over = true;
+            return outcome;
+        }
+        let reds_potted = potted.iter().filter(|&&ball| ball == RED).count() as u32;
+        self.reds = self.reds.saturating_sub(reds_potted);
+        let on_black = self.on == BallOn::Clearance(BLACK);
+        let black_potted = potted.contains(&BLACK);
+
+        if let Some(foul) = self.foul(first, &potted) {
+            let points = self.foul_points(first, &potted).max(MIN_FOUL_POINTS);
+            self.scores[1 - self.player] += points;
+            outcome.foul = Some((foul, points));
+            outcome.respot = potted
+                .iter()
+                .copied()
+                .filter(|&ball| ball >= YELLOW)
+                .collect();
+            self.next_turn();
+            self.over = on_black;
+        } else if potted.is_empty() {
+            self.next_turn();
+        } else {
+            outcome.points = potted.iter().map(|&ball| ball as u32).sum();
+            self.scores[self.player] += outcome.points;
+            self.on = match self.on {
+                BallOn::Red => BallOn::Color,
+                BallOn::Color => {
+                    outcome.respot = potted;
+                    if self.reds > 0 {
+                        BallOn::Red
+                    } else {
+                        BallOn::Clearance(YELLOW)
+                    }
+                }
+                BallOn::Clearance(color) => BallOn::Clearance(color + 1),
+            };
+            self.over = on_black;
+        }
+
+        // a tie on the last black is played off with the black respotted
+        if self.over && self.scores[0] == self.scores[1] {
+            self.over = false;
+            self.on = BallOn::Clearance(BLACK);
+            if black_potted && !outcome.respot.contains(&BLACK) {
+                outcome.respot.push(BLACK);
+            }
+        }
+        outcome.frame_over = self.over;
+        outcome
+    }
+
+    fn scores(&self) -> [u32; 2] {
+        self.scores
+    }
+
+    fn player(&self) -> usize {
+        self.player
+    }
+
+    fn ball_on(&self) -> String {
+        match self.on {
+            BallOn::Red => "a red".to_string(),
+            BallOn::Color => "a color".to_string(),
+            BallOn::Clearance(color) => format!("the {}", color_name(color)),
+        }
+    }
+
+    fn winner(&self) -> Option<usize> {
+        if !self.over {
+            return None;
+        }
+        Some(if self.scores[0] > self.scores[1] {
+            0
+        } else {
+            1
+        })
+    }
+}
+
+fn color_name(ball: u8) -> &'static str {
+    match ball {
+        YELLOW => "yellow",
+        GREEN => "green",
+        BROWN => "brown",
+        BLUE => "blue",
+        PINK => "pink",
+        _ => "black",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::shot;
+
+    #[test]
+    fn reds_and_colors_alternate() {
+        let mut frame = SnookerFrame::new(2);
+        assert_eq!(shot(&mut frame, Some(RED), &[RED]).points, 1);
+        assert_eq!(frame.on, BallOn::Color);
+
+        let outcome = shot(&mut frame, Some(PINK), &[PINK]);
+        assert_eq!(outcome.points, 6);
+        assert_eq!(outcome.respot, vec![PINK]);
+        assert_eq!(frame.on, BallOn::Red);
+
+        assert_eq!(shot(&mut frame, Some(RED), &[RED]).points, 1);
+        // the color after the last red is respotted too
+        let outcome = shot(&mut frame, Some(BLACK), &[BLACK]);
+        assert_eq!(outcome.respot, vec![BLACK]);
+        assert_eq!(frame.on, BallOn::Clearance(YELLOW));
+        assert_eq!(frame.scores(), [15, 0]);
+        assert_eq!(frame.player(), 0);
+    }
+
+    #[test]
+    fn a_miss_ends_the_turn_and_the_ball_on_is_a_red_again() {
+        let mut frame = SnookerFrame::new(3);
+        shot(&mut frame, Some(RED), &[RED]);
+        let outcome = shot(&mut frame, Some(BLUE), &[]);
+        assert_eq!(outcome.foul, None);
+        assert_eq!(frame.player(), 1);
+        assert_eq!(frame.on, BallOn::Red);
+    }
+
+    #[test]
+    fn fouls_give_the_opponent_at_least_four() {
+        let mut frame = SnookerFrame::new(15);
+        let outcome = shot(&mut frame, None, &[]);
+        assert_eq!(outcome.foul, Some((Foul::NoBallHit, 4)));
+        assert_eq!(frame.scores(), [0, 4]);
+        assert_eq!(frame.player(), 1);
+
+        let outcome = shot(&mut frame, Some(BLUE), &[]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(BLUE), 5)));
+        assert_eq!(frame.scores(), [5, 4]);
+
+        let outcome = shot(&mut frame, Some(RED), &[RED, CUE]);
+        assert_eq!(outcome.foul, Some((Foul::CueBallPotted, 4)));
+        assert_eq!(frame.scores(), [5, 8]);
+
+        // the pink goes back on its spot
+        let outcome = shot(&mut frame, Some(RED), &[RED, PINK]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallPotted(PINK), 6)));
+        assert_eq!(outcome.respot, vec![PINK]);
+        assert_eq!(frame.scores(), [11, 8]);
+        assert_eq!(frame.player(), 0);
+    }
+
+    #[test]
+    fn a_foul_on_a_color_is_worth_the_color_hit() {
+        let mut frame = SnookerFrame::new(15);
+        shot(&mut frame, Some(RED), &[RED]);
+        let outcome = shot(&mut frame, Some(GREEN), &[GREEN, BLACK]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallPotted(BLACK), 7)));
+        assert_eq!(outcome.respot, vec![GREEN, BLACK]);
+        assert_eq!(frame.on, BallOn::Red);
+        assert_eq!(frame.player(), 1);
+    }
+
+    #[test]
+    fn the_colors_are_cleared_in_order() {
+        let mut frame = SnookerFrame::new(0);
+        let outcome = shot(&mut frame, Some(GREEN), &[GREEN]);
+        assert_eq!(outcome.foul, Some((Foul::WrongBallFirst(GREEN), 4)));
+        assert_eq!(outcome.respot, vec![GREEN]);
+        assert_eq!(frame.player(), 1);
+
+        for color in YELLOW..BLACK {
+            let outcome = shot(&mut frame, Some(color), &[color]);
+            assert_eq!(outcome.points, color as u32);
+            assert!(outcome.respot.is_empty());
+            assert!(!outcome.frame_over);
+        }
+        assert_eq!(frame.on, BallOn::Clearance(BLACK));
+        assert_eq!(frame.ball_on(), "the black");
+    }
+
+    #[test]
+    fn potting_the_last_black_ends_the_frame() {
+        let mut frame = SnookerFrame::new(0);
+        for color in YELLOW..BLACK {
+            shot(&mut frame, Some(color), &[color]);
+        }
+        let outcome = shot(&mut frame, Some(BLACK), &[BLACK]);
+        assert!(outcome.frame_over);
+        assert_eq!(frame.scores(), [27, 0]);
+        assert_eq!(frame.winner(), Some(0));
+        assert!(shot(&mut frame, None, &[]).frame_over);
+    }
+
+    #[test]
+    fn a_foul_on_the_last_black_ends_the_frame() {
+        let mut frame = SnookerFrame::new(0);
+        for color in YELLOW..BLACK {
+            shot(&mut frame, Some(color), &[color]);
+        }
+        let outcome = shot(&mut frame, Some(BLACK), &[CUE]);
+        assert_eq!(outcome.foul, Some((Foul::CueBallPotted, 7)));
+        assert!(outcome.frame_over);
+        assert_eq!(frame.winner(), Some(0));
+    }
+
+    #[test]
+    fn a_tie_is_played_off_with_the_black_respotted() {
+        let mut frame = SnookerFrame::new(0);
+        for color in YELLOW..BLACK {
+            shot(&mut frame, Some(color), &[color]);
+        }
+        // seven behind with only the black left
+        frame.scores = [20, 27];
+        let outcome = shot(&mut frame, Some(BLACK), &[BLACK]);
+        assert_eq!(frame.scores(), [27, 27]);
+        assert!(!outcome.frame_over);
+        assert_eq!(outcome.respot, vec![BLACK]);
+        assert_eq!(frame.on, BallOn::Clearance(BLACK));
+        assert_eq!(frame.winner(), None);
+
+        shot(&mut frame, Some(BLACK), &[]);
+        assert_eq!(frame.player(), 1);
+        let outcome = shot(&mut frame, Some(BLACK), &[BLACK]);
+        assert!(outcome.frame_over);
+        assert_eq!(frame.winner(), Some(1));
+    }
+}
@@ -19,6 +19,9 @@ pub fn de_select(commands: &mut Commands, entity: Entity) {
         .insert(Selection { selected: false });
 }
 
+// what can be picked with the cursor and gets highlighted
+type Selectable = Or<(With<Ball>, With<Pocket>)>;
+
 #[derive(Component)]
 pub struct Highlight;
 
@@ -26,10 +29,10 @@ pub fn highlight_selected(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    to_highlight_q: Query<(&mut Selection, Entity, Option<&Children>, Or<(With<Ball>, With<Pocket>)>)>,
+    to_highlight_q: Query<(&Selection, Entity, Option<&Children>), Selectable>,
     highlight_q: Query<&Highlight>,
 ) {
-    for (selection, entity, children, _) in &to_highlight_q {
+    for (selection, entity, children) in &to_highlight_q {
         if let Some(children) = children {
             if let Some(child) = children
                 .iter()
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+
+use crate::{
+    config::CONFIG,
+    rules::{BLACK, BLUE, BROWN, GREEN, PINK, YELLOW},
+};
+
+// On a full size table the baulk line is 737mm from the baulk cushion and the
+// D has a radius of 292mm, out of 3569mm between the cushions.
+const BAULK_LINE: f32 = 737.0 / 3569.0;
+const D_RADIUS: f32 = 292.0 / 3569.0;
+// and the black spot is 324mm from the top cushion
+const BLACK_SPOT: f32 = 324.0 / 3569.0;
+// how far apart the spots tried for a ball in the D are
+const D_STEP: f32 = 4.0;
+// between the balls of a rack, so they don't start out touching
+const RACK_GAP: f32 = 0.5;
+
+// the inside of the cushions, baulk is on the left
+pub fn playing_area() -> Rect {
+    Rect::from_center_half_size(
+        Vec2::ZERO,
+        CONFIG.table_size.as_vec2() / 2.0 - CONFIG.wall_width,
+    )
+}
+
+pub fn baulk_line_x() -> f32 {
+    let area = playing_area();
+    area.min.x + area.width() * BAULK_LINE
+}
+
+pub fn d_radius() -> f32 {
+    playing_area().width() * D_RADIUS
+}
+
+pub fn d_center() -> Vec2 {
+    Vec2::new(baulk_line_x(), 0.0)
+}
+
+pub fn is_in_d(position: Vec2) -> bool {
+    position.x <= baulk_line_x() && position.distance(d_center()) <= d_radius()
+}
+
+// Seen from the baulk end green, brown and yellow are on the baulk line from
+// left to right, blue in the middle of the table, pink halfway between it and
+// the top cushion and black close to that cushion.
+pub fn color_spot(ball: u8) -> Option<Vec2> {
+    let area = playing_area();
+    let spot = match ball {
+        YELLOW => Vec2::new(baulk_line_x(), -d_radius()),
+        GREEN => Vec2::new(baulk_line_x(), d_radius()),
+        BROWN => d_center(),
+        BLUE => area.center(),
+        PINK => foot_spot(),
+        BLACK => Vec2::new(area.max.x - area.width() * BLACK_SPOT, 0.0),
+        _ => return None,
+    };
+    Some(spot)
+}
+
+// where the front ball of a pool rack goes, halfway between the middle of the
+// table and the top cushion
+pub fn foot_spot() -> Vec2 {
+    Vec2::new(playing_area().max.x / 2.0, 0.0)
+}
+
+// A ball in a rack of rows pointing at the baulk end, with the given number of
+// balls in its row. The row and the ball are counted from front and from the
+// bottom cushion.
+pub fn rack_spot(front: Vec2, row: usize, ball: usize, balls_in_row: usize) -> Vec2 {
+    let spacing = 2.0 * CONFIG.ball_radius + RACK_GAP;
+    let across = ball as f32 - (balls_in_row - 1) as f32 / 2.0;
+    front + Vec2::new(row as f32 * spacing * 3f32.sqrt() / 2.0, across * spacing)
+}
+
+// whether a ball there would touch none of the others
+fn is_free(spot: Vec2, occupied: &[Vec2]) -> bool {
+    occupied
+        .iter()
+        .all(|ball| ball.distance(spot) > 2.0 * CONFIG.ball_radius)
+}
+
+// A color goes back on its own spot, if a ball is in the way on the highest
+// free one, and if all of them are taken as close to its own spot as it can
+// towards the top cushion.
+pub fn respot_position(ball: u8, occupied: &[Vec2]) -> Option<Vec2> {
+    let own = color_spot(ball)?;
+    std::iter::once(own)
+        .chain((YELLOW..=BLACK).rev().filter_map(color_spot))
+        .find(|&spot| is_free(spot, occupied))
+        .or_else(|| free_spot_behind(own, occupied))
+}
+
+// the spot closest to the given one towards the top cushion where a ball
+// touches none of the others
+pub fn free_spot_behind(spot: Vec2, occupied: &[Vec2]) -> Option<Vec2> {
+    let end = playing_area().max.x - CONFIG.ball_radius;
+    (0..)
+        .map(|step| spot + Vec2::X * step as f32)
+        .take_while(|spot| spot.x < end)
+        .find(|&spot| is_free(spot, occupied))
+}
+
+// The spot in the D closest to its middle where a ball touches none of the
+// others, looking further out in rings.
+pub fn free_spot_in_d(occupied: &[Vec2]) -> Option<Vec2> {
+    let rings = (d_radius() / D_STEP) as i32;
+    (0..=rings).find_map(|ring| {
+        let radius = ring as f32 * D_STEP;
+        let count = (ring * 6).max(1);
+        (0..count)
+            .map(|i| {
+                let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+                d_center() + Vec2::from_angle(angle) * radius
+            })
+            .find(|&spot| is_in_d(spot) && is_free(spot, occupied))
+    })
+}
+
+pub fn draw_table_markings(mut gizmos: Gizmos) {
+    let area = playing_area();
+    let x = baulk_line_x();
+    gizmos.line_2d(
+        Vec2::new(x, area.min.y),
+        Vec2::new(x, area.max.y),
+        Color::WHITE,
+    );
+    gizmos.arc_2d(
+        d_center(),
+        -std::f32::consts::FRAC_PI_2,
+        std::f32::consts::PI,
+        d_radius(),
+        Color::WHITE,
+    );
+}
@@ -1,4 +1,4 @@
-use crate::config::CONFIG;
+use crate::{config::CONFIG, physics::cushion_physics};
 use bevy::prelude::*;
 
 #[derive(Component)]
@@ -78,3 +78,35 @@ pub fn setup_walls(
     ));
 
 }
+
+// The cushions stop where the pockets cut into them. Rails just outside the
+// window keep a ball that goes into a pocket from leaving the table.
+pub fn setup_cushions(mut commands: Commands) {
+    let half_table = CONFIG.table_size.as_vec2() / 2.0;
+    let half_width = CONFIG.wall_width / 2.0;
+    // the pockets are in the middle of the walls
+    let edge = half_table - half_width;
+    // half the gap a pocket leaves in the face of a cushion
+    let mouth = (CONFIG.pocket_radius.powi(2) - half_width.powi(2)).sqrt();
+
+    let mut spawn = |center: Vec2, half_size: Vec2| {
+        commands.spawn((
+            cushion_physics(half_size),
+            TransformBundle::from(Transform::from_translation(center.extend(0.0))),
+        ));
+    };
+    for y in [-edge.y, edge.y] {
+        for x in [-edge.x / 2.0, edge.x / 2.0] {
+            spawn(Vec2::new(x, y), Vec2::new(edge.x / 2.0 - mouth, half_width));
+        }
+    }
+    for x in [-edge.x, edge.x] {
+        spawn(Vec2::new(x, 0.0), Vec2::new(half_width, edge.y - mouth));
+    }
+    for y in [-half_table.y - half_width, half_table.y + half_width] {
+        spawn(Vec2::new(0.0, y), Vec2::new(half_table.x + CONFIG.wall_width, half_width));
+    }
+    for x in [-half_table.x - half_width, half_table.x + half_width] {
+        spawn(Vec2::new(x, 0.0), Vec2::new(half_width, half_table.y + CONFIG.wall_width));
+    }
+}